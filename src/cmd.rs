@@ -11,11 +11,16 @@
 /// which get stringified, literals or expressions in parentheses. The first set of arguments is
 /// also used in error reporting ("Failed to run `cargo generate-lockfile`").
 ///
+/// The second set of arguments may additionally contain expressions in braces, which get spread
+/// as multiple arguments (e.g. `{options.args()}` for any `IntoIterator` of arguments).
+///
 /// If there are no further arguments, the second set of brackets is omitted.
 ///
 /// Additionally, it may output a boolean (where the returned status code is either `0` mapped to
 /// `true` or `1` mapped to `false`) by adding `-> bool`, or alternatively the stdout output
-/// excluding a single trailing newline if it exists by adding `-> String`.
+/// excluding a single trailing newline if it exists by adding `-> String`. With `-> Output`, it
+/// doesn't fail for unsuccessful status codes, and instead returns the [`std::process::Output`]
+/// with the captured stdout & stderr output.
 ///
 /// It may also be run in another working directory using `in path` (after potential return
 /// specifiers as explained above), where `path` is an expression of the type
@@ -24,11 +29,18 @@ macro_rules! cmd {
     (@arg $ident:ident) => { stringify!($ident) };
     (@arg $literal:literal) => { $literal };
     (@arg ($expr:expr)) => { $expr };
+    (@args {$expr:expr}) => { $expr };
+    (@args $arg:tt) => { [$crate::cmd::cmd!(@arg $arg)] };
     (@stdout $cmd:ident -> String) => { std::process::Stdio::piped() };
+    (@stdout $cmd:ident -> Output) => { std::process::Stdio::piped() };
     (@stdout $cmd:ident $(-> $ty:ident)?) => { std::io::stderr() };
+    (@stderr $cmd:ident -> Output) => { $cmd.stderr(std::process::Stdio::piped()); };
+    (@stderr $cmd:ident $(-> $ty:ident)?) => {};
     (@success $out:ident -> bool) => { true };
+    (@success $out:ident -> Output) => { true };
     (@success $out:ident $(-> $ty:ident)?) => { $out.status.success() };
     (@out $out:ident -> bool) => { $out.status.success() };
+    (@out $out:ident -> Output) => { $out };
     (@out $out:ident -> String) => {{
         let mut out = $out.stdout;
 
@@ -44,7 +56,7 @@ macro_rules! cmd {
         let cmd_args: [&str;_] = [$($crate::cmd::cmd!(@arg $cmd_args)),*];
         let mut cmd = std::process::Command::new(cmd0);
         cmd.args(&cmd_args)
-            $($(.arg($crate::cmd::cmd!(@arg $args)))?)?;
+            $($(.args($crate::cmd::cmd!(@args $args)))*)?;
 
         $(
             if let Some(path) = $path {
@@ -53,16 +65,16 @@ macro_rules! cmd {
         )?
 
         cmd.stdout($crate::cmd::cmd!(@stdout cmd $(-> $ret)?));
+        $crate::cmd::cmd!(@stderr cmd $(-> $ret)?);
 
         let output = cmd.spawn()?.wait_with_output()?;
 
         if !$crate::cmd::cmd!(@success output $(-> $ret)?) {
-            color_eyre::eyre::bail!(
-                "Failed to run `{} {}`, returned status code {}",
-                cmd0,
-                cmd_args.join(" "),
-                output.status,
-            );
+            return Err($crate::ResolveDiffError::CommandFailed {
+                command: format!("{} {}", cmd0, cmd_args.join(" ")),
+                status: output.status,
+            }
+            .into());
         }
 
         <color_eyre::Result<_>>::Ok($crate::cmd::cmd!(@out output $(-> $ret)?))
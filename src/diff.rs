@@ -3,10 +3,14 @@
 //! Generate a diff between two [`resolve::Resolved`]s, see [`Diff::between`].
 
 use crate::Platform;
+use crate::lockfile::LockfileFormatChange;
+use crate::major_updates::BreakingChange;
 use crate::resolve::{
     DependencyKind, IncludedDependencyReason, IncludedDependencyVersion, Reasons, Resolved,
-    SpecificCrateIdent,
+    SpecificCrateIdent, StructuredReason, structured_reasons,
 };
+use camino::Utf8PathBuf;
+use cargo_metadata::Edition;
 use semver::Version;
 use serde::Serialize;
 use std::collections::{BTreeMap, BTreeSet};
@@ -23,8 +27,30 @@ pub struct Added<'a> {
     pub is_proc_macro: bool,
     /// The platform this dependency is built (and potentially run at build time) for
     pub platforms: &'a BTreeSet<Platform>,
+    /// This dependency is only included for some of the platforms that were resolved for
+    pub platform_specific: bool,
+    /// This dependency is not included for the base platform, see [`Diff::with_base_platform`]
+    pub off_base_platform: bool,
     /// The reasons for the inclusion of this dependency
     pub reasons: &'a Reasons,
+    /// The `reasons` as nested objects, see [`Diff::with_structured_reasons`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_reasons: Option<Vec<StructuredReason<'a>>>,
+    /// The `description` of the package, see [`Diff::with_package_metadata`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The `repository` of the package, see [`Diff::with_package_metadata`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+}
+
+/// The direction of the version change of a [`Comparison`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum Direction {
+    Upgrade,
+    Downgrade,
+    /// The version didn't change, only the kind or set of platforms did
+    Sideways,
 }
 
 /// Dependencies on the right that are different from dependencies with the same name on the left
@@ -38,7 +64,20 @@ pub struct Comparison<'a> {
     pub is_proc_macro: bool,
     /// The platform this dependency is built (and potentially run at build time) for
     pub platforms: &'a BTreeSet<Platform>,
+    /// This dependency is only included for some of the platforms that were resolved for
+    pub platform_specific: bool,
+    /// This dependency is not included for the base platform, see [`Diff::with_base_platform`]
+    pub off_base_platform: bool,
     pub reasons: &'a Reasons,
+    /// The `reasons` as nested objects, see [`Diff::with_structured_reasons`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured_reasons: Option<Vec<StructuredReason<'a>>>,
+    /// The `description` of the package, see [`Diff::with_package_metadata`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The `repository` of the package, see [`Diff::with_package_metadata`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
 
     /// The closest version from the left, or [`None`] if the same version existed (in this case
     /// [`Comparison`]s are only emitted if the `kind` or set of platforms changed)
@@ -46,6 +85,10 @@ pub struct Comparison<'a> {
     /// The list of all other versions from the left that are different from this version _and_
     /// different from `closest_different_old_version`
     pub all_other_old_versions: Vec<Version>,
+    /// Whether the change from `closest_different_old_version` is semver-incompatible
+    pub breaking: Option<BreakingChange>,
+    /// The direction of the change from `closest_different_old_version`
+    pub direction: Direction,
 
     /// The platforms this version was not built for on the left, but is now, with the reasons for
     /// the addition
@@ -57,15 +100,6 @@ pub struct Comparison<'a> {
     pub added_in_non_debug: BTreeMap<&'a IncludedDependencyReason, &'a BTreeSet<Platform>>,
 }
 
-impl Comparison<'_> {
-    fn requires_review(&self) -> bool {
-        self.closest_different_old_version.is_some()
-            || !self.added_in_platforms.is_empty()
-            || !self.added_in_build.is_empty()
-            || !self.added_in_non_debug.is_empty()
-    }
-}
-
 /// Removed dependencies on the right
 ///
 /// These only get emitted if no comparison was emitted for this dependency
@@ -75,40 +109,288 @@ pub struct Removed {
     pub ident: SpecificCrateIdent,
     /// The remaining versions of the same name included on the right
     pub remaining_versions: Vec<Version>,
+    /// The distinct parents that depended on this crate on the left, see
+    /// [`Diff::with_reverse_dependencies`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependents: Option<BTreeSet<String>>,
 }
 
-/// The differences (for code reviews of dependencies) between two dependency resolutions
+/// A removed crate correlated with an added crate of another name, see [`Diff::with_renames`]
+#[derive(Serialize, Debug)]
+pub struct Renamed<'a> {
+    /// The name of the crate on the left
+    pub old_name: String,
+    /// The versions of the crate that were removed
+    pub old_versions: Vec<Version>,
+    /// The added crate on the right
+    pub new: Added<'a>,
+}
+
+/// All added, changed & removed versions of a single crate, see [`Diff::with_crate_groups`]
+#[derive(Serialize, Debug, Default)]
+pub struct CrateChanges {
+    pub added: Vec<Version>,
+    pub changed: Vec<ChangedVersion>,
+    pub removed: Vec<Version>,
+}
+
+/// A changed version of a crate within [`CrateChanges`]
+#[derive(Serialize, Debug)]
+pub struct ChangedVersion {
+    /// See [`Comparison::closest_different_old_version`]
+    pub old: Option<Version>,
+    pub new: Version,
+}
+
+/// An added or changed crate on the right with a new or different `links` key than on the left,
+/// which can cause conflicts between crates linking the same native library
+#[derive(Serialize, Debug)]
+pub struct LinksChange {
+    /// The name & version on the right
+    pub ident: SpecificCrateIdent,
+    /// The `links` value on the right
+    pub links: Option<String>,
+    /// The closest version on the left, if the crate was included there
+    pub old_version: Option<Version>,
+    /// The `links` value of `old_version`
+    pub old_links: Option<String>,
+}
+
+/// A workspace member that is part of both sides with a different `edition`, which can change
+/// the dependencies that get resolved
+#[derive(Serialize, Debug)]
+pub struct EditionChange {
+    pub old: Edition,
+    pub new: Edition,
+}
+
+/// A crate for which the set of included versions changed differently on both sides of a
+/// three-way comparison, see [`Conflict::between`]
 #[derive(Serialize, Debug)]
+pub struct Conflict {
+    pub name: String,
+    /// The versions included in the common base
+    pub base_versions: Vec<Version>,
+    /// The versions included on the left
+    pub left_versions: Vec<Version>,
+    /// The versions included on the right
+    pub right_versions: Vec<Version>,
+}
+
+impl Conflict {
+    /// Returns the crates that changed on both sides relative to `base`, ending up with a
+    /// different set of included versions on the `left` than on the `right`
+    pub fn between(base: &Resolved, left: &Resolved, right: &Resolved) -> Vec<Self> {
+        let versions = |resolved: &Resolved, name: &str| {
+            resolved
+                .crate_versions(name)
+                .map(|versions| versions.keys().cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+
+        let names = [base, left, right]
+            .into_iter()
+            .flat_map(Resolved::all_crate_names)
+            .collect::<BTreeSet<_>>();
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let base_versions = versions(base, name);
+                let left_versions = versions(left, name);
+                let right_versions = versions(right, name);
+
+                let conflicting = left_versions != base_versions
+                    && right_versions != base_versions
+                    && left_versions != right_versions;
+                conflicting.then(|| Conflict {
+                    name: name.to_owned(),
+                    base_versions,
+                    left_versions,
+                    right_versions,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The differences (for code reviews of dependencies) between two dependency resolutions
+///
+/// [`Diff::default`] is an empty diff.
+#[derive(Serialize, Debug, Default)]
 pub struct Diff<'a> {
     pub added: Vec<Added<'a>>,
     pub changed: Vec<Comparison<'a>>,
     pub removed: Vec<Removed>,
+    /// Removed crates correlated with added ones, see [`Diff::with_renames`]
+    pub renamed: Vec<Renamed<'a>>,
+    /// The versions of `added`, `changed` & `removed` grouped by crate name, see
+    /// [`Diff::with_crate_groups`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_crate: Option<BTreeMap<String, CrateChanges>>,
+    /// Crate versions that are part of both sides without any change relevant for reviews, see
+    /// [`Diff::with_unchanged`]
+    pub unchanged: Vec<SpecificCrateIdent>,
     /// Crate versions that are part of the right but not the left, which weren't included in the
     /// platforms the resolution ran for
     pub filtered_added: Vec<SpecificCrateIdent>,
     /// Crate versions that are part of the left but not the right, which weren't included in the
     /// platforms the resolution ran for
     pub filtered_removed: Vec<SpecificCrateIdent>,
+    /// Crates with more than one version included on the right, see [`Resolved::duplicates`]
+    pub duplicates: BTreeMap<String, Vec<Version>>,
+    /// The workspace manifests (as in [`IncludedDependencyReason::root`]) that lead to the
+    /// inclusion of any added, changed or removed crate (on the left for removed ones)
+    pub touched_members: BTreeSet<Utf8PathBuf>,
+    /// Crates that are part of the [`Resolved::proc_macro_closure`] on the right, but not on the
+    /// left
+    pub new_proc_macro_closure: BTreeSet<SpecificCrateIdent>,
+    /// The `resolver` version in effect on the right, see [`IndexedMetadata::resolver`]
+    ///
+    /// [`IndexedMetadata::resolver`]: crate::indexed::IndexedMetadata::resolver
+    pub resolver: String,
+    /// Workspace members (by package name) whose `edition` changed
+    pub edition_changes: BTreeMap<String, EditionChange>,
+    /// Crates with a `links` key that are new on the right, or for which it changed
+    pub links_changes: Vec<LinksChange>,
+    /// The change of the `Cargo.lock` format version, if it changed, see
+    /// [`IndexedMetadata::lockfile_version`]
+    ///
+    /// [`IndexedMetadata::lockfile_version`]: crate::indexed::IndexedMetadata::lockfile_version
+    pub lockfile_format_change: Option<LockfileFormatChange>,
+    /// The number of added, changed, removed & renamed entries suppressed with [`Diff::ignore`]
+    pub ignored: usize,
+    /// The number of added, changed & removed entries of other dependency kinds suppressed with
+    /// [`Diff::retain_kinds`]
+    pub kind_filtered: usize,
+}
+
+/// Only the number of added, changed & removed crate versions between two [`Resolved`]s, see
+/// [`DiffSummary::between`]
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    /// The number of added, changed & removed entries that were left out, see [`Diff::ignore`]
+    pub ignored: usize,
+}
+
+impl DiffSummary {
+    /// The number of added, changed & removed crate versions (without `ignored` ones)
+    pub fn change_count(&self) -> usize {
+        self.added + self.changed + self.removed
+    }
+
+    /// Returns the number of entries [`Diff::between`] would produce (after [`Diff::ignore`]
+    /// with `ignored`), without collecting the reasons & platforms of every entry
+    pub fn between(old: &Resolved, new: &Resolved, ignored: &[String]) -> Self {
+        let mut summary = DiffSummary::default();
+        let mut count = |name: &String, field: fn(&mut DiffSummary) -> &mut usize, n| {
+            if ignored.contains(name) {
+                summary.ignored += n;
+            } else {
+                *field(&mut summary) += n;
+            }
+        };
+
+        for (name, new_versions) in &new.included {
+            match old.included.get(name) {
+                None => count(name, |s| &mut s.added, new_versions.len()),
+                Some(old_versions) => {
+                    let changed = (new_versions.iter())
+                        .filter(|(version, info)| requires_review(old_versions, version, info))
+                        .count();
+                    count(name, |s| &mut s.changed, changed);
+                }
+            }
+        }
+
+        for (name, old_versions) in &old.included {
+            let new_versions = new.included.get(name);
+            // NOTE: Like in `Diff::between`, this isn't a removal if a new version is included
+            let has_change = new_versions
+                .is_some_and(|new| new.keys().any(|key| !old_versions.contains_key(key)));
+            if has_change {
+                continue;
+            }
+
+            let removed = (old_versions.keys())
+                .filter(|version| !new_versions.is_some_and(|new| new.contains_key(*version)))
+                .count();
+            count(name, |s| &mut s.removed, removed);
+        }
+
+        summary
+    }
+}
+
+/// Returns the version from `old` that `new_version` gets compared against, see
+/// [`Comparison::closest_different_old_version`]
+fn closest_old_version<'a>(
+    old: &'a BTreeMap<Version, IncludedDependencyVersion>,
+    new_version: &Version,
+) -> (&'a Version, &'a IncludedDependencyVersion) {
+    // NOTE: The assumption is that checking for removals is probably usually easier,
+    // so giving out downgrades for reviews is preferred:
+    old.range(new_version..).next().unwrap_or_else(|| {
+        old.last_key_value()
+            .expect("Higher ones were already checked, version set is never empty")
+    })
+}
+
+/// Returns whether a version is listed as changed (with a [`Comparison`]) instead of unchanged,
+/// without building the [`Comparison`]
+///
+/// This is the case if it has a `closest_different_old_version`, or any `added_in_platforms`,
+/// `added_in_build` or `added_in_non_debug`.
+fn requires_review(
+    old: &BTreeMap<Version, IncludedDependencyVersion>,
+    new_version: &Version,
+    new: &IncludedDependencyVersion,
+) -> bool {
+    let (closest_old_version, closest_old_info) = closest_old_version(old, new_version);
+
+    let any_reason = |f: fn(DependencyKind) -> bool| new.reasons.keys().any(|r| f(r.kind));
+
+    closest_old_version != new_version
+        || !new.platforms.is_subset(&closest_old_info.platforms)
+        || (new.kind.is_build_active()
+            && !closest_old_info.kind.is_build_active()
+            && any_reason(DependencyKind::is_build_active))
+        || (new.kind.is_release_included()
+            && closest_old_info.kind.is_test_only()
+            && any_reason(DependencyKind::is_release_included))
+}
+
+/// Returns whether a dependency is only included for some of the platforms that were resolved for
+fn is_platform_specific(resolved: &Resolved, platforms: &BTreeSet<Platform>) -> bool {
+    !resolved.platforms.is_empty() && *platforms != resolved.platforms
 }
 
 impl<'a> Diff<'a> {
     fn compare(
+        new_resolved: &Resolved,
         name: &'a str,
         old: &'a BTreeMap<Version, IncludedDependencyVersion>,
         new_version: Version,
         new: &'a IncludedDependencyVersion,
     ) -> Comparison<'a> {
-        // NOTE: The assumption is that checking for removals is probably usually easier,
-        // so giving out downgrades for reviews is preferred:
-        let (closest_old_version, closest_old_info) =
-            old.range(&new_version..).next().unwrap_or_else(|| {
-                old.last_key_value()
-                    .expect("Higher ones were already checked, version set is never empty")
-            });
+        let (closest_old_version, closest_old_info) = closest_old_version(old, &new_version);
 
         let closest_different_old_version =
             (*closest_old_version != new_version).then(|| closest_old_version.clone());
 
+        let breaking = closest_different_old_version
+            .as_ref()
+            .and_then(|old_version| BreakingChange::between(old_version, &new_version));
+
+        let direction = match closest_different_old_version {
+            None => Direction::Sideways,
+            Some(ref old_version) if *old_version < new_version => Direction::Upgrade,
+            Some(_) => Direction::Downgrade,
+        };
+
         let all_other_old_versions =
             if let Some(ref already_mentioned) = closest_different_old_version {
                 old.keys()
@@ -134,20 +416,21 @@ impl<'a> Diff<'a> {
             })
             .collect();
 
-        let added_in_build = if new.kind.run_at_build && !closest_old_info.kind.run_at_build {
-            new.reasons
-                .iter()
-                .filter(|(reason, _)| reason.kind.run_at_build)
-                .collect()
-        } else {
-            BTreeMap::new()
-        };
+        let added_in_build =
+            if new.kind.is_build_active() && !closest_old_info.kind.is_build_active() {
+                new.reasons
+                    .iter()
+                    .filter(|(reason, _)| reason.kind.is_build_active())
+                    .collect()
+            } else {
+                BTreeMap::new()
+            };
 
         let added_in_non_debug =
-            if !new.kind.only_debug_builds && closest_old_info.kind.only_debug_builds {
+            if new.kind.is_release_included() && closest_old_info.kind.is_test_only() {
                 new.reasons
                     .iter()
-                    .filter(|(reason, _)| !reason.kind.only_debug_builds)
+                    .filter(|(reason, _)| reason.kind.is_release_included())
                     .collect()
             } else {
                 BTreeMap::new()
@@ -162,10 +445,17 @@ impl<'a> Diff<'a> {
             has_build_rs: new.has_build_rs,
             is_proc_macro: new.is_proc_macro,
             platforms: &new.platforms,
+            platform_specific: is_platform_specific(new_resolved, &new.platforms),
+            off_base_platform: false,
             reasons: &new.reasons,
+            structured_reasons: None,
+            description: None,
+            repository: None,
 
             closest_different_old_version,
             all_other_old_versions,
+            breaking,
+            direction,
 
             added_in_platforms,
             added_in_build,
@@ -173,9 +463,256 @@ impl<'a> Diff<'a> {
         }
     }
 
+    /// Returns the comparison for a specific crate version, if it changed
+    pub fn comparison_for(&self, name: &str, version: &Version) -> Option<&Comparison<'a>> {
+        self.changed.iter().find(|comparison| {
+            comparison.ident.name == name && comparison.ident.version == *version
+        })
+    }
+
+    /// Returns the changed entries with a semver-incompatible version change, see
+    /// [`Comparison::breaking`]
+    pub fn breaking_changes(&self) -> impl Iterator<Item = &Comparison<'a>> {
+        (self.changed.iter()).filter(|comparison| comparison.breaking.is_some())
+    }
+
+    /// Designate `base` as the primary platform of this diff
+    ///
+    /// Added & changed dependencies that aren't included for `base` get marked as
+    /// `off_base_platform`, and are moved behind the ones that are (keeping the order otherwise).
+    pub fn with_base_platform(&mut self, base: &Platform) {
+        for added in &mut self.added {
+            added.off_base_platform = !added.platforms.contains(base);
+        }
+        for comparison in &mut self.changed {
+            comparison.off_base_platform = !comparison.platforms.contains(base);
+        }
+
+        self.added.sort_by_key(|added| added.off_base_platform);
+        self.changed
+            .sort_by_key(|comparison| comparison.off_base_platform);
+    }
+
+    /// The number of added, changed, removed & renamed entries
+    pub fn change_count(&self) -> usize {
+        self.added.len() + self.changed.len() + self.removed.len() + self.renamed.len()
+    }
+
+    /// Whether there are no added, changed, removed or renamed entries (regardless of unchanged,
+    /// filtered or ignored ones)
+    pub fn is_empty(&self) -> bool {
+        self.change_count() == 0
+    }
+
+    /// Remove all entries for crates with any of the given names, counting the added, changed,
+    /// removed & renamed ones in `ignored`
+    ///
+    /// Renamed entries are removed if either name is ignored. `touched_members` stays as is, since
+    /// other crates may still lead to the same members.
+    pub fn ignore(&mut self, names: &[String]) {
+        let before = self.change_count();
+
+        let keep_name = |name: &String| !names.contains(name);
+        let keep = |ident: &SpecificCrateIdent| keep_name(&ident.name);
+        self.added.retain(|added| keep(&added.ident));
+        self.changed.retain(|comparison| keep(&comparison.ident));
+        self.removed.retain(|removed| keep(&removed.ident));
+        self.renamed
+            .retain(|renamed| keep_name(&renamed.old_name) && keep(&renamed.new.ident));
+        if let Some(ref mut by_crate) = self.by_crate {
+            by_crate.retain(|name, _| keep_name(name));
+        }
+        self.unchanged.retain(keep);
+        self.filtered_added.retain(keep);
+        self.filtered_removed.retain(keep);
+        self.duplicates.retain(|name, _| keep_name(name));
+        self.new_proc_macro_closure.retain(keep);
+        self.links_changes.retain(|change| keep(&change.ident));
+
+        self.ignored += before - self.change_count();
+    }
+
+    /// Only keep the added, changed & removed entries whose [`DependencyKind`] (on the left for
+    /// removed entries) matches `keep`, counting the others in `kind_filtered`
+    ///
+    /// Unlike [`ResolveOptions::kinds`], this doesn't change what gets resolved, so crates that
+    /// only changed their kind are still compared across all dependency edges.
+    ///
+    /// [`ResolveOptions::kinds`]: crate::resolve::ResolveOptions::kinds
+    pub fn retain_kinds(
+        &mut self,
+        old: &Resolved,
+        new: &Resolved,
+        keep: impl Fn(DependencyKind) -> bool,
+    ) {
+        let before = self.added.len() + self.changed.len() + self.removed.len();
+
+        let kind_in = |resolved: &Resolved, ident: &SpecificCrateIdent| {
+            (resolved.crate_versions(&ident.name))
+                .and_then(|versions| versions.get(&ident.version))
+                .map(IncludedDependencyVersion::kind)
+        };
+        self.added.retain(|added| keep(added.kind));
+        self.changed.retain(|comparison| keep(comparison.kind));
+        self.removed
+            .retain(|removed| kind_in(old, &removed.ident).is_none_or(&keep));
+        self.links_changes
+            .retain(|change| kind_in(new, &change.ident).is_none_or(&keep));
+
+        self.kind_filtered += before - (self.added.len() + self.changed.len() + self.removed.len());
+    }
+
+    /// Only keep the changed entries with the given [`Direction`], leaving added & removed
+    /// entries as is
+    pub fn retain_direction(&mut self, direction: Direction) {
+        self.changed
+            .retain(|comparison| comparison.direction == direction);
+    }
+
+    /// Correlate removed crates with added crates as [`Renamed`] entries, for pairs of old & new
+    /// names that are known to replace each other
+    ///
+    /// Every added version of the new name gets its own entry (listing all removed versions of the
+    /// old name), and pairs where either side is missing are ignored.
+    pub fn with_renames(&mut self, renames: &[(String, String)]) {
+        for (old_name, new_name) in renames {
+            let is_removed = |removed: &Removed| removed.ident.name == *old_name;
+            let is_added = |added: &Added<'_>| added.ident.name == *new_name;
+            if !self.removed.iter().any(is_removed) || !self.added.iter().any(is_added) {
+                continue;
+            }
+
+            let old_versions = (self.removed.iter())
+                .filter(|removed| is_removed(removed))
+                .map(|removed| removed.ident.version.clone())
+                .collect::<Vec<_>>();
+            self.removed.retain(|removed| !is_removed(removed));
+
+            let (added, kept) = std::mem::take(&mut self.added)
+                .into_iter()
+                .partition::<Vec<_>, _>(is_added);
+            self.added = kept;
+
+            self.renamed.extend(added.into_iter().map(|new| Renamed {
+                old_name: old_name.clone(),
+                old_versions: old_versions.clone(),
+                new,
+            }));
+        }
+    }
+
+    /// Additionally list the crate versions that are part of both sides without any change
+    /// relevant for reviews as `unchanged`
+    ///
+    /// `old` & `new` have to be the sides this diff was created from.
+    pub fn with_unchanged(&mut self, old: &Resolved, new: &Resolved) {
+        self.unchanged = (new.included.iter())
+            .filter_map(|(name, new_versions)| {
+                old.included
+                    .get(name)
+                    .map(|old_versions| (name, old_versions, new_versions))
+            })
+            .flat_map(|(name, old_versions, new_versions)| {
+                (new_versions.iter())
+                    .filter(|(version, info)| !requires_review(old_versions, version, info))
+                    .map(move |(version, _)| SpecificCrateIdent {
+                        name: name.clone(),
+                        version: version.clone(),
+                    })
+            })
+            .collect();
+    }
+
+    /// Additionally list the versions of all added, changed & removed entries grouped by crate
+    /// name as `by_crate`, so several versions of the same crate can be reviewed together
+    pub fn with_crate_groups(&mut self) {
+        let mut by_crate = BTreeMap::<_, CrateChanges>::new();
+        for added in &self.added {
+            (by_crate.entry(added.ident.name.clone()).or_default().added)
+                .push(added.ident.version.clone());
+        }
+        for comparison in &self.changed {
+            let changed = ChangedVersion {
+                old: comparison.closest_different_old_version.clone(),
+                new: comparison.ident.version.clone(),
+            };
+            (by_crate
+                .entry(comparison.ident.name.clone())
+                .or_default()
+                .changed)
+                .push(changed);
+        }
+        for removed in &self.removed {
+            (by_crate
+                .entry(removed.ident.name.clone())
+                .or_default()
+                .removed)
+                .push(removed.ident.version.clone());
+        }
+        self.by_crate = Some(by_crate);
+    }
+
+    /// Add the reasons of added & changed dependencies as nested objects (see
+    /// [`StructuredReason`]) next to their string form
+    pub fn with_structured_reasons(&mut self) {
+        for added in &mut self.added {
+            added.structured_reasons = Some(structured_reasons(added.reasons));
+        }
+        for comparison in &mut self.changed {
+            comparison.structured_reasons = Some(structured_reasons(comparison.reasons));
+        }
+    }
+
+    /// Add the distinct parents that depended on removed crates in `old` (the left side of this
+    /// diff) as `dependents`
+    pub fn with_reverse_dependencies(&mut self, old: &Resolved) {
+        for removed in &mut self.removed {
+            let reasons = old
+                .crate_versions(&removed.ident.name)
+                .and_then(|versions| versions.get(&removed.ident.version))
+                .into_iter()
+                .flat_map(|info| info.reasons.keys());
+            removed.dependents = Some(reasons.map(|reason| reason.parent.to_string()).collect());
+        }
+    }
+
+    /// Add the `description` & `repository` of added & changed dependencies from the manifests
+    /// of the packages in `new` (the right side of this diff)
+    pub fn with_package_metadata(&mut self, new: &Resolved) {
+        let packages = new
+            .full_metadata
+            .packages
+            .values()
+            .map(|package| ((package.name.as_str(), &package.version), package))
+            .collect::<BTreeMap<_, _>>();
+        let metadata = |ident: &SpecificCrateIdent| {
+            packages
+                .get(&(ident.name.as_str(), &ident.version))
+                .map_or((None, None), |package| {
+                    (package.description.clone(), package.repository.clone())
+                })
+        };
+
+        for added in &mut self.added {
+            (added.description, added.repository) = metadata(&added.ident);
+        }
+        for comparison in &mut self.changed {
+            (comparison.description, comparison.repository) = metadata(&comparison.ident);
+        }
+    }
+
     /// Returns the differences between two [`Resolved`]s for code reviews of dependencies
+    ///
+    /// The two sides don't need to come from the same workspace: [crates.io] dependencies are
+    /// compared by name & version only, and local paths (in reasons) are relative to the
+    /// workspace root of their own side. Renamed or moved workspace members therefore only show
+    /// up as different reasons, not as changed dependencies.
     pub fn between(old: &'a Resolved, new: &'a Resolved) -> Self {
-        let added = new
+        if Self::same_inclusions(old, new) {
+            return Self::without_changes(old, new);
+        }
+
+        let added: Vec<_> = new
             .included
             .iter()
             .filter(|(name, _)| !old.included.contains_key(*name))
@@ -193,7 +730,12 @@ impl<'a> Diff<'a> {
                 has_build_rs: info.has_build_rs,
                 is_proc_macro: info.is_proc_macro,
                 platforms: &info.platforms,
+                platform_specific: is_platform_specific(new, &info.platforms),
+                off_base_platform: false,
                 reasons: &info.reasons,
+                structured_reasons: None,
+                description: None,
+                repository: None,
             })
             .collect();
 
@@ -206,14 +748,18 @@ impl<'a> Diff<'a> {
                     .map(|old_versions| (name, old_versions, new_versions))
             })
             .flat_map(|(name, old_versions, new_versions)| {
-                new_versions.iter().map(move |(new_version, new_info)| {
-                    Self::compare(name, old_versions, new_version.clone(), new_info)
-                })
+                (new_versions.iter())
+                    .map(move |(new_version, new_info)| (name, old_versions, new_version, new_info))
             })
-            .filter(|comparison| comparison.requires_review())
-            .collect();
+            .filter(|(_, old_versions, new_version, new_info)| {
+                requires_review(old_versions, new_version, new_info)
+            })
+            .map(|(name, old_versions, new_version, new_info)| {
+                Self::compare(new, name, old_versions, new_version.clone(), new_info)
+            })
+            .collect::<Vec<_>>();
 
-        let removed = old
+        let removed: Vec<_> = old
             .included
             .iter()
             .filter_map(|(name, versions)| {
@@ -247,6 +793,7 @@ impl<'a> Diff<'a> {
                             version: version.clone(),
                         },
                         remaining_versions: remaining_versions.clone(),
+                        dependents: None,
                     })
             })
             .collect();
@@ -264,12 +811,115 @@ impl<'a> Diff<'a> {
         let filtered_added = in_right_set(&old.filtered, &new.filtered);
         let filtered_removed = in_right_set(&old.filtered, &new.filtered);
 
+        let removed_reasons = removed
+            .iter()
+            .filter_map(|removed| {
+                old.crate_versions(&removed.ident.name)?
+                    .get(&removed.ident.version)
+            })
+            .flat_map(|info| info.reasons.keys());
+        let touched_members = added
+            .iter()
+            .flat_map(|added| added.reasons.keys())
+            .chain(changed.iter().flat_map(|changed| changed.reasons.keys()))
+            .chain(removed_reasons)
+            .map(|reason| reason.root.clone())
+            .collect();
+
+        let old_proc_macro_closure = old.proc_macro_closure();
+        let new_proc_macro_closure = new
+            .proc_macro_closure()
+            .into_iter()
+            .filter(|ident| !old_proc_macro_closure.contains(ident))
+            .collect();
+
+        let links = |resolved: &Resolved, name: &str, version: &Version| {
+            let info = resolved.crate_versions(name)?.get(version)?;
+            info.links.clone()
+        };
+        let added_links = added.iter().filter_map(|added| {
+            Some(LinksChange {
+                ident: added.ident.clone(),
+                links: Some(links(new, &added.ident.name, &added.ident.version)?),
+                old_version: None,
+                old_links: None,
+            })
+        });
+        let changed_links = changed.iter().filter_map(|comparison| {
+            let ident = &comparison.ident;
+            let old_version = comparison.closest_different_old_version.clone()?;
+            let new_links = links(new, &ident.name, &ident.version);
+            let old_links = links(old, &ident.name, &old_version);
+            (new_links != old_links).then(|| LinksChange {
+                ident: ident.clone(),
+                links: new_links,
+                old_version: Some(old_version),
+                old_links,
+            })
+        });
+        let links_changes = added_links.chain(changed_links).collect();
+
         Diff {
             added,
             changed,
             removed,
+            renamed: Vec::new(),
+            by_crate: None,
+            unchanged: Vec::new(),
             filtered_added,
             filtered_removed,
+            duplicates: new.duplicates(),
+            touched_members,
+            new_proc_macro_closure,
+            resolver: new.full_metadata.resolver.clone(),
+            edition_changes: edition_changes(old, new),
+            links_changes,
+            lockfile_format_change: lockfile_format_change(old, new),
+            ignored: 0,
+            kind_filtered: 0,
         }
     }
+
+    /// Whether both sides include & filter exactly the same crate versions (with the same kinds,
+    /// platforms & reasons), checking the cheap differences before comparing everything
+    fn same_inclusions(old: &Resolved, new: &Resolved) -> bool {
+        std::ptr::eq(old, new)
+            || (old.included.len() == new.included.len()
+                && old.filtered.len() == new.filtered.len()
+                && old.included.keys().next() == new.included.keys().next()
+                && old.included == new.included
+                && old.filtered == new.filtered)
+    }
+
+    /// The diff between two sides without any added, changed or removed crates (see
+    /// [`Diff::same_inclusions`]), which is the same as the one the full comparison produces
+    fn without_changes(old: &Resolved, new: &'a Resolved) -> Self {
+        Diff {
+            duplicates: new.duplicates(),
+            resolver: new.full_metadata.resolver.clone(),
+            edition_changes: edition_changes(old, new),
+            lockfile_format_change: lockfile_format_change(old, new),
+            ..Diff::default()
+        }
+    }
+}
+
+/// The workspace members whose `edition` differs between both sides
+fn edition_changes(old: &Resolved, new: &Resolved) -> BTreeMap<String, EditionChange> {
+    let old_editions = old.member_editions();
+    new.member_editions()
+        .into_iter()
+        .filter_map(|(name, new)| {
+            let old = *old_editions.get(&name)?;
+            (old != new).then_some((name, EditionChange { old, new }))
+        })
+        .collect()
+}
+
+/// The change of the `Cargo.lock` format version between both sides, if any
+fn lockfile_format_change(old: &Resolved, new: &Resolved) -> Option<LockfileFormatChange> {
+    LockfileFormatChange::between(
+        old.full_metadata.lockfile_version,
+        new.full_metadata.lockfile_version,
+    )
 }
@@ -2,31 +2,80 @@
 
 //! Git helpers for the application to add changes & commit them
 
+use crate::ResolveDiffError;
 use crate::cmd::cmd;
 use color_eyre::Result;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 
+/// What `HEAD` currently points to, see [`Repository::current_branch_or_commit`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Head {
+    /// A checked out branch, with its name
+    Branch(String),
+    /// A detached `HEAD`, with the commit ID
+    Detached(String),
+}
+
+impl Head {
+    /// The branch name or commit ID, which can be checked out to return to this state
+    pub fn as_str(&self) -> &str {
+        match self {
+            Head::Branch(name) => name,
+            Head::Detached(commit) => commit,
+        }
+    }
+}
+
+impl fmt::Display for Head {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// A `git` repository
 pub struct Repository {
     /// The path to the repository
     path: Option<PathBuf>,
     /// If any changes got `git add`ed to the repository
     dirty: bool,
+    /// The last commit made via [`Repository::commit`]
+    last_commit: Option<String>,
 }
 
 impl Repository {
     /// Open an existing [`Repository`] at the given path.
     ///
     /// This does not check if the repository actually exist, methods on this type will simply fail
-    /// if it doesn't.
+    /// if it doesn't (see [`Repository::open`] for that).
     pub fn new(path: Option<PathBuf>) -> Self {
-        Repository { path, dirty: false }
+        Repository {
+            path,
+            dirty: false,
+            last_commit: None,
+        }
+    }
+
+    /// Open an existing [`Repository`] at the given path (or the current directory), failing with
+    /// [`ResolveDiffError::NotARepository`] if it isn't inside of a `git` repository
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let output = cmd!([git "rev-parse"] ["--git-dir"] -> Output in &path)?;
+        if !output.status.success() {
+            let path = path.unwrap_or_else(|| PathBuf::from("."));
+            return Err(ResolveDiffError::NotARepository(path).into());
+        }
+        Ok(Self::new(path))
+    }
+
+    /// Whether a given path has changes that weren't `git add`ed yet
+    pub fn has_changes(&self, path: &Path) -> Result<bool> {
+        Ok(!cmd!([git diff] ["-s" "--exit-code" "--" (path)] -> bool in &self.path)?)
     }
 
     /// `git add` a given path if it includes changes.
     pub fn add(&mut self, path: &Path) -> Result<()> {
-        let changed = !cmd!([git diff] ["-s" "--exit-code" "--" (path)] -> bool in &self.path)?;
-        if changed {
+        if self.has_changes(path)? {
             self.dirty = true;
             cmd!([git add] [(path)] in &self.path)?;
         }
@@ -48,21 +97,165 @@ impl Repository {
         }
         cmd!([git commit] ["-m" (message)] in &self.path)?;
         self.dirty = false;
-        Ok(Some(self.current_commit()?))
+        let commit = self.current_commit()?;
+        self.last_commit = Some(commit.clone());
+        Ok(Some(commit))
+    }
+
+    /// Returns the ID of the last commit made via [`Repository::commit`], if any
+    pub fn last_commit(&self) -> Option<&str> {
+        self.last_commit.as_deref()
     }
 
-    /// Returns the current branch, if any, or the current commit ID
-    pub fn current_branch_or_commit(&self) -> Result<String> {
+    /// `git tag` the current commit, creating an annotated tag if a message is given
+    pub fn tag(&self, name: &str, message: Option<&str>) -> Result<()> {
+        match message {
+            Some(message) => cmd!([git tag] ["-a" (name) "-m" (message)] in &self.path),
+            None => cmd!([git tag] [(name)] in &self.path),
+        }
+    }
+
+    /// `git stash push` all local changes, returning whether a stash was actually created (which
+    /// isn't the case for a clean tree)
+    ///
+    /// Only pass `true` on to [`Repository::stash_pop`].
+    pub fn stash_push(&self) -> Result<bool> {
+        let stash =
+            || cmd!([git "rev-parse"] ["-q" "--verify" "refs/stash"] -> Output in &self.path);
+        let before = stash()?.stdout;
+        cmd!([git stash push] in &self.path)?;
+        Ok(stash()?.stdout != before)
+    }
+
+    /// `git stash pop` the stash created by [`Repository::stash_push`] if it created one
+    pub fn stash_pop(&self, created: bool) -> Result<()> {
+        if created {
+            cmd!([git stash pop] in &self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the current branch, if any, or the current commit ID for a detached `HEAD`
+    pub fn current_branch_or_commit(&self) -> Result<Head> {
         let branch = cmd!([git branch] ["--show-current"] -> String in &self.path)?;
         if !branch.is_empty() {
-            Ok(branch)
+            Ok(Head::Branch(branch))
+        } else {
+            Ok(Head::Detached(self.current_commit()?))
+        }
+    }
+
+    /// `git fetch` everything from `remote`, unshallowing the repository if it is a shallow clone
+    pub fn fetch(&self, remote: &str) -> Result<()> {
+        // NOTE: `--unshallow` fails for complete repositories
+        let is_shallow =
+            cmd!([git "rev-parse"] ["--is-shallow-repository"] -> String in &self.path)? == "true";
+        if is_shallow {
+            cmd!([git fetch] ["--unshallow" (remote)] in &self.path)
         } else {
-            Ok(self.current_commit()?)
+            cmd!([git fetch] [(remote)] in &self.path)
         }
     }
 
+    /// Returns the commit ID a revision points to
+    pub fn commit_of(&self, rev: &str) -> Result<String> {
+        cmd!([git "rev-parse"] [(format!("{rev}^{{commit}}"))] -> String in &self.path)
+    }
+
+    /// Returns the contents of a file (relative to the repository path) at a given revision,
+    /// without checking it out
+    pub fn show_file(&self, rev: &str, path: &Path) -> Result<String> {
+        let object = format!("{rev}:./{}", path.display());
+        cmd!([git show] [(object)] -> String in &self.path)
+    }
+
+    /// Returns the commit ID of the best common ancestor of two revisions
+    pub fn merge_base(&self, a: &str, b: &str) -> Result<String> {
+        cmd!([git "merge-base"] [(a) (b)] -> String in &self.path)
+    }
+
     /// Checks out a given branch or commit ID
     pub fn checkout(&mut self, target: &str) -> Result<()> {
         cmd!([git "checkout"] [(target)] in &self.path)
     }
+
+    /// Checks out a given commit ID as a detached `HEAD`
+    pub fn checkout_detached(&mut self, commit: &str) -> Result<()> {
+        cmd!([git "checkout"] ["--detach" (commit)] in &self.path)
+    }
+
+    /// Record the current branch or commit ID, returning a [`HeadGuard`] that checks it back out
+    /// when dropped.
+    pub fn pin_head(&mut self) -> Result<HeadGuard<'_>> {
+        let target = self.current_branch_or_commit()?;
+        Ok(HeadGuard {
+            repository: self,
+            target,
+            armed: true,
+        })
+    }
+}
+
+/// A guard that checks out a previously recorded branch or commit ID when dropped, see
+/// [`Repository::pin_head`].
+///
+/// Errors during the checkout on drop are only reported on `stderr`, use [`HeadGuard::restore`]
+/// to handle them instead.
+pub struct HeadGuard<'a> {
+    repository: &'a mut Repository,
+    target: Head,
+    armed: bool,
+}
+
+impl HeadGuard<'_> {
+    /// The branch or commit ID that gets restored
+    pub fn target(&self) -> &Head {
+        &self.target
+    }
+
+    /// Check out the recorded branch or commit ID, without disarming the guard
+    ///
+    /// A detached `HEAD` is restored with `--detach`, so a branch that happens to share the name of
+    /// the commit ID doesn't get checked out instead.
+    pub fn return_to_target(&mut self) -> Result<()> {
+        match &self.target {
+            Head::Branch(name) => self.repository.checkout(name),
+            Head::Detached(commit) => self.repository.checkout_detached(commit),
+        }
+    }
+
+    /// Check out the recorded branch or commit ID and disarm the guard
+    pub fn restore(mut self) -> Result<()> {
+        self.armed = false;
+        self.return_to_target()
+    }
+
+    /// Disarm the guard, so the recorded branch or commit ID doesn't get checked out on drop
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Deref for HeadGuard<'_> {
+    type Target = Repository;
+
+    fn deref(&self) -> &Repository {
+        self.repository
+    }
+}
+
+impl DerefMut for HeadGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Repository {
+        self.repository
+    }
+}
+
+impl Drop for HeadGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed
+            && let Err(error) = self.return_to_target()
+        {
+            eprintln!("Failed to check out `{}` again: {error}", self.target);
+        }
+    }
 }
@@ -2,12 +2,17 @@
 
 //! Run `cargo metadata` & index the result by [`PackageId`]s
 
-use std::{collections::HashMap, path::Path};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::{fs, io};
 
-use crate::Platform;
-use camino::Utf8PathBuf;
-use cargo_metadata::{MetadataCommand, Node, Package, PackageId};
-use color_eyre::Result;
+use crate::lockfile::format_version;
+use crate::toml_edit::{MutableTomlFile, TomlPathLookup};
+use crate::util::{CargoOptions, locate_workspace_manifest};
+use crate::{Platform, ResolveDiffError};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{Edition, MetadataCommand, Node, Package, PackageId};
+use color_eyre::{Result, eyre::WrapErr};
 
 /// The indexed output of `cargo metadata`
 #[derive(Debug)]
@@ -22,11 +27,23 @@ pub struct IndexedMetadata {
     pub resolve: HashMap<PackageId, Node>,
     /// The root directory of the workspace (the directory containing the manifest)
     pub workspace_root: Utf8PathBuf,
+    /// The manifest of the workspace root, as located by `cargo locate-project --workspace`
+    pub root_manifest: Utf8PathBuf,
     /// The list of members in this workspace
     pub workspace_members: Vec<PackageId>,
     /// The default members of this workspace. Contrary to [`cargo_metadata`], this is represented
     /// as an `Option` instead of panicking on access if it was missing.
     pub workspace_default_members: Option<Vec<PackageId>>,
+    /// The `resolver` version in effect for the workspace (such as `"2"`)
+    ///
+    /// This is `workspace.resolver` or `package.resolver` from the root manifest if it is set,
+    /// and otherwise the one implied by the `edition` of the root package (or `"1"` for virtual
+    /// workspaces).
+    pub resolver: String,
+    /// The format version of the `Cargo.lock` of the workspace, see [`format_version`]
+    ///
+    /// This is also `None` if there is no `Cargo.lock`.
+    pub lockfile_version: Option<u32>,
 }
 
 impl IndexedMetadata {
@@ -34,17 +51,38 @@ impl IndexedMetadata {
     /// with the given platform (via `--filter-platform`).
     ///
     /// If `platform` is `None`, this contains all packages for all platforms.
-    pub fn gather(path: &Path, platform: Option<Platform>) -> Result<Self> {
+    pub fn gather(path: &Path, platform: Option<Platform>, options: &CargoOptions) -> Result<Self> {
+        Self::gather_for(path, platform, options, None)
+    }
+
+    /// [`IndexedMetadata::gather`], taking the `root_manifest`, `resolver` & `lockfile_version`
+    /// from `workspace` (gathered for the same workspace before) instead of locating & reading the
+    /// root manifest & the `Cargo.lock` again, if it is given
+    fn gather_for(
+        path: &Path,
+        platform: Option<Platform>,
+        options: &CargoOptions,
+        workspace: Option<&IndexedMetadata>,
+    ) -> Result<Self> {
         let mut other_options = Vec::new();
         if let Some(ref platform) = platform {
             other_options.extend(["--filter-platform".to_owned(), platform.0.clone()]);
         }
-        other_options.push("--locked".to_owned());
+        if !options.unlocked {
+            other_options.push("--locked".to_owned());
+        }
+        other_options.extend(options.resolve_features.args().map(str::to_owned));
+        other_options.extend(options.args().map(str::to_owned));
 
+        let timing = options.time("metadata", || {
+            platform.as_ref().map(|platform| platform.0.clone())
+        });
         let data = MetadataCommand::new()
+            .cargo_path(options.cargo())
             .manifest_path(path)
             .other_options(other_options)
             .exec()?;
+        drop(timing);
 
         let packages = data
             .packages
@@ -52,33 +90,206 @@ impl IndexedMetadata {
             .map(|pkg| (pkg.id.clone(), pkg))
             .collect();
 
-        let resolve = data.resolve.map_or_else(HashMap::new, |resolve| {
-            resolve
-                .nodes
-                .into_iter()
-                .map(|node| (node.id.clone(), node))
-                .collect()
-        });
+        // NOTE: `cargo metadata` only omits the resolve graph with `--no-deps`, but all of the
+        // resolution relies on it being present:
+        let Some(resolve) = data.resolve else {
+            return Err(ResolveDiffError::IncompleteMetadata(format!(
+                "no resolve graph for {path:?} (as with `--no-deps`)"
+            ))
+            .into());
+        };
+        let resolve = resolve
+            .nodes
+            .into_iter()
+            .map(|node| (node.id.clone(), node))
+            .collect();
 
         let workspace_default_members = data
             .workspace_default_members
             .is_available()
             .then(|| (*data.workspace_default_members).to_owned());
 
+        let (root_manifest, resolver, lockfile_version) = match workspace {
+            Some(workspace) => (
+                workspace.root_manifest.clone(),
+                workspace.resolver.clone(),
+                workspace.lockfile_version,
+            ),
+            None => {
+                let root_manifest =
+                    Utf8PathBuf::try_from(locate_workspace_manifest(path, options)?)?;
+                let resolver = Self::read_resolver(&root_manifest, &packages)?;
+                let lockfile_version = Self::read_lockfile_version(&data.workspace_root)?;
+                (root_manifest, resolver, lockfile_version)
+            }
+        };
+
         Ok(IndexedMetadata {
             platform,
             packages,
             resolve,
             workspace_root: data.workspace_root,
+            root_manifest,
             workspace_members: data.workspace_members,
             workspace_default_members,
+            resolver,
+            lockfile_version,
+        })
+    }
+
+    /// [`IndexedMetadata::gather`] for several platforms at once, running one `cargo metadata`
+    /// process per platform concurrently
+    ///
+    /// The `resolver` & `lockfile_version` are taken from `workspace` (gathered for the same
+    /// workspace before), instead of reading them again for every platform. This doesn't limit
+    /// the concurrency itself, see [`CargoOptions::threads`].
+    pub fn gather_concurrently(
+        path: &Path,
+        platforms: &[Platform],
+        workspace: &IndexedMetadata,
+        options: &CargoOptions,
+    ) -> Result<Vec<Self>> {
+        std::thread::scope(|scope| {
+            let handles = platforms
+                .iter()
+                .map(|platform| {
+                    scope.spawn(|| {
+                        Self::gather_for(path, Some(platform.clone()), options, Some(workspace))
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("gathering metadata doesn't panic"))
+                .collect()
         })
     }
 
+    /// Whether `cargo metadata` produced exactly the same packages & resolve graph for both (e.g.
+    /// for two platforms that resolve identically), regardless of the platforms they ran for
+    pub fn resolves_identically(&self, other: &Self) -> bool {
+        self.packages.len() == other.packages.len()
+            && self.resolve.len() == other.resolve.len()
+            && self.workspace_root == other.workspace_root
+            && self.workspace_members == other.workspace_members
+            && self.workspace_default_members == other.workspace_default_members
+            && self.resolve == other.resolve
+            && self.packages == other.packages
+    }
+
+    /// Add platform-specific metadata to groups of metadata that [resolve
+    /// identically](Self::resolves_identically), keeping the first of every group with the
+    /// platforms of all of them
+    ///
+    /// Metadata without a specific platform is never merged.
+    pub fn merge_identical(
+        mut out: Vec<(Self, Vec<Platform>)>,
+        metadata: impl IntoIterator<Item = Self>,
+    ) -> Vec<(Self, Vec<Platform>)> {
+        for metadata in metadata {
+            let Some(ref platform) = metadata.platform else {
+                out.push((metadata, Vec::new()));
+                continue;
+            };
+
+            let identical = out.iter_mut().find(|(other, _)| {
+                other.platform.is_some() && other.resolves_identically(&metadata)
+            });
+            match identical {
+                Some((_, platforms)) => platforms.push(platform.clone()),
+                None => {
+                    let platforms = vec![platform.clone()];
+                    out.push((metadata, platforms));
+                }
+            }
+        }
+        out
+    }
+
+    /// Read the format version of the `Cargo.lock` in the workspace root, if there is one
+    fn read_lockfile_version(workspace_root: &Utf8Path) -> Result<Option<u32>> {
+        let path = workspace_root.join("Cargo.lock");
+        match fs::read_to_string(&path) {
+            Ok(contents) => format_version(&contents)
+                .wrap_err_with(|| format!("Failed to read the format version of {path:?}")),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Read the `resolver` version from `workspace.resolver` or `package.resolver` in the root
+    /// manifest, defaulting to the one implied by the `edition` of the root package (or `"1"` for
+    /// virtual workspaces)
+    fn read_resolver(
+        root_manifest: &Utf8Path,
+        packages: &HashMap<PackageId, Package>,
+    ) -> Result<String> {
+        let manifest = MutableTomlFile::open(root_manifest)?;
+
+        let explicit = [["workspace", "resolver"], ["package", "resolver"]]
+            .into_iter()
+            .find_map(|path| manifest.path_lookup(path));
+        if let Some(resolver) = explicit {
+            let resolver = resolver
+                .as_str()
+                .ok_or_else(|| ResolveDiffError::InvalidManifest {
+                    path: root_manifest.to_owned().into(),
+                    message: "Invalid `resolver` value".to_owned(),
+                })?;
+            return Ok(resolver.to_owned());
+        }
+
+        let root_package = packages
+            .values()
+            .find(|package| package.manifest_path == root_manifest);
+        let resolver = match root_package.map(|package| package.edition) {
+            None | Some(Edition::E2015 | Edition::E2018) => "1",
+            Some(Edition::E2021) => "2",
+            Some(_) => "3",
+        };
+        Ok(resolver.to_owned())
+    }
+
+    /// Returns the package of the root manifest, if the workspace isn't virtual
+    pub fn root_package(&self) -> Option<&Package> {
+        (self.packages.values()).find(|package| package.manifest_path == self.root_manifest)
+    }
+
     /// Return the default members, or if they are missing, all workspace members
     pub fn get_workspace_default_members(&self) -> &[PackageId] {
         self.workspace_default_members
             .as_ref()
             .unwrap_or(self.workspace_members.as_ref())
     }
+
+    /// Return the `edition` of every workspace member by package name
+    pub fn member_editions(&self) -> BTreeMap<String, Edition> {
+        self.workspace_members
+            .iter()
+            .map(|pkg| {
+                let package = &self.packages[pkg];
+                (package.name.to_string(), package.edition)
+            })
+            .collect()
+    }
+
+    /// Return the workspace members matching the given package names or package IDs, or the
+    /// default members if `selection` is empty
+    pub fn select_workspace_members(&self, selection: &[String]) -> Result<Vec<&PackageId>> {
+        if selection.is_empty() {
+            return Ok(self.get_workspace_default_members().iter().collect());
+        }
+
+        selection
+            .iter()
+            .map(|selected| {
+                self.workspace_members
+                    .iter()
+                    .find(|pkg| {
+                        pkg.repr == *selected || self.packages[*pkg].name.as_str() == selected
+                    })
+                    .ok_or_else(|| ResolveDiffError::NotAMember(selected.clone()).into())
+            })
+            .collect()
+    }
 }
@@ -17,17 +17,123 @@
 //! point to a branch or are manually updated by someone else.
 
 use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::ExitStatus;
 
 /// A platform tuple (such as `x86_64-unknown-linux-gnu`)
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
 #[serde(transparent)]
 pub struct Platform(pub String);
 
+/// The main classes of failures of this crate
+///
+/// The APIs of this crate return [`color_eyre::Report`]s instead of this error directly, to keep
+/// the context added to them. Every failure originating in this crate is one of these errors (see
+/// [`color_eyre::Report::downcast_ref`]), while failures of dependencies (such as `cargo
+/// metadata`, the [crates.io] API, parsing TOML or versions, or I/O) keep their own error types.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ResolveDiffError {
+    /// Running an external command (such as `git` or `cargo`) returned an unsuccessful status
+    CommandFailed { command: String, status: ExitStatus },
+    /// A manifest doesn't have the expected shape
+    InvalidManifest { path: PathBuf, message: String },
+    /// A selected package isn't a member of the workspace
+    NotAMember(String),
+    /// A directory isn't (inside of) a `git` repository
+    NotARepository(PathBuf),
+    /// A crate identifier isn't of the form `<name> <version>`, see
+    /// [`resolve::SpecificCrateIdent`]
+    InvalidCrateIdent { ident: String, message: String },
+    /// Manifests of the workspace are outside of the workspace root
+    ExternalManifests {
+        workspace_root: PathBuf,
+        manifests: Vec<PathBuf>,
+    },
+    /// A `Cargo.lock` doesn't have the expected shape, with the part that is invalid
+    InvalidLockfile(String),
+    /// The output of `cargo metadata` misses parts the resolution relies on
+    IncompleteMetadata(String),
+    /// The `build.target` configured for `cargo` is invalid, with where it was configured
+    InvalidBuildTarget(String),
+    /// A `cargo update` (or the `cargo check` after it) failed where the failure can't be reported
+    /// otherwise
+    UpdateFailed(util::FailedUpdate),
+    /// A crate version (as `<name> <version>`) couldn't be downloaded from the registry
+    NotDownloaded(String),
+    /// A crate version (as `<name> <version>`) was published without a `Cargo.lock`
+    NoPublishedLockfile(String),
+    /// Rolling back changes to manifests (see
+    /// [`major_updates::ManifestDependencySet::roll_back`]) or to a `git` checkout failed
+    RollbackFailed(Vec<color_eyre::Report>),
+}
+
+impl fmt::Display for ResolveDiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveDiffError::CommandFailed { command, status } => {
+                write!(
+                    f,
+                    "Failed to run `{command}`, returned status code {status}"
+                )
+            }
+            ResolveDiffError::InvalidManifest { path, message } => {
+                write!(f, "Invalid manifest {path:?}: {message}")
+            }
+            ResolveDiffError::NotAMember(selected) => {
+                write!(f, "`{selected}` is not a member of the workspace")
+            }
+            ResolveDiffError::NotARepository(path) => {
+                write!(f, "{path:?} is not inside of a `git` repository")
+            }
+            ResolveDiffError::InvalidCrateIdent { ident, message } => {
+                write!(f, "Invalid crate identifier {ident:?}: {message}")
+            }
+            ResolveDiffError::ExternalManifests {
+                workspace_root,
+                manifests,
+            } => write!(
+                f,
+                "Manifests outside of the workspace root {workspace_root:?}: {manifests:?}"
+            ),
+            ResolveDiffError::InvalidLockfile(part) => {
+                write!(f, "Invalid {part} in the `Cargo.lock`")
+            }
+            ResolveDiffError::IncompleteMetadata(message) => {
+                write!(f, "Incomplete `cargo metadata` output: {message}")
+            }
+            ResolveDiffError::InvalidBuildTarget(source) => {
+                write!(f, "Invalid `build.target` {source}")
+            }
+            ResolveDiffError::UpdateFailed(failure) => {
+                let command = match failure.reason {
+                    util::UpdateFailure::UpdateFailed => "cargo update",
+                    util::UpdateFailure::CheckFailed => "cargo check",
+                };
+                write!(f, "`{command}` failed:\n{}", failure.error)
+            }
+            ResolveDiffError::NotDownloaded(ident) => {
+                write!(f, "`{ident}` wasn't downloaded from the registry")
+            }
+            ResolveDiffError::NoPublishedLockfile(ident) => {
+                write!(f, "`{ident}` was published without a `Cargo.lock`")
+            }
+            ResolveDiffError::RollbackFailed(errors) => {
+                write!(f, "Failed to roll back:\n{errors:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveDiffError {}
+
 mod cmd;
 
 pub mod diff;
 pub mod git;
 pub mod indexed;
+pub mod lockfile;
 pub mod major_updates;
 pub mod resolve;
 pub mod toml_edit;
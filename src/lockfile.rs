@@ -0,0 +1,159 @@
+// Copyright (C) 2026 by GiGa infosystems
+
+//! A lightweight comparison of the packages locked in two `Cargo.lock`s (without running
+//! `cargo metadata`), see [`LockfileDiff::between`]
+
+use crate::ResolveDiffError;
+use color_eyre::Result;
+use semver::Version;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A package in a `Cargo.lock`, identified by its name & `source` (`None` for workspace members &
+/// path dependencies)
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub source: Option<String>,
+}
+
+/// Added packages, or added versions of a package
+#[derive(Serialize, Debug)]
+pub struct LockedVersions {
+    #[serde(flatten)]
+    pub package: LockedPackage,
+    pub versions: Vec<Version>,
+}
+
+/// A package that is locked on both sides, but with different versions
+#[derive(Serialize, Debug)]
+pub struct LockedChange {
+    #[serde(flatten)]
+    pub package: LockedPackage,
+    pub old_versions: Vec<Version>,
+    pub new_versions: Vec<Version>,
+}
+
+/// A change of the format `version` of a `Cargo.lock`, see [`format_version`]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockfileFormatChange {
+    pub old: Option<u32>,
+    pub new: Option<u32>,
+}
+
+impl LockfileFormatChange {
+    /// Returns the change between two format versions, or `None` if they are the same
+    pub fn between(old: Option<u32>, new: Option<u32>) -> Option<Self> {
+        (old != new).then_some(LockfileFormatChange { old, new })
+    }
+}
+
+/// The differences between the packages locked in two `Cargo.lock`s
+///
+/// Unlike [`Diff`](crate::diff::Diff), this doesn't consider platforms, dependency kinds or reasons
+/// for inclusion, since it only relies on the lockfiles.
+#[derive(Serialize, Debug)]
+pub struct LockfileDiff {
+    pub added: Vec<LockedVersions>,
+    pub changed: Vec<LockedChange>,
+    pub removed: Vec<LockedVersions>,
+    pub lockfile_format_change: Option<LockfileFormatChange>,
+}
+
+/// Returns the format version of the contents of a `Cargo.lock` (its top-level `version` key)
+///
+/// This is `None` for formats before version 3, which didn't have that key yet.
+pub fn format_version(lockfile: &str) -> Result<Option<u32>> {
+    let document = lockfile.parse::<toml_edit::DocumentMut>()?;
+    let Some(version) = document.get("version") else {
+        return Ok(None);
+    };
+
+    let version = version
+        .as_integer()
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or_else(|| ResolveDiffError::InvalidLockfile("`version`".to_owned()))?;
+    Ok(Some(version))
+}
+
+/// Returns the locked versions of all packages in the contents of a `Cargo.lock`
+pub fn locked_packages(lockfile: &str) -> Result<BTreeMap<LockedPackage, BTreeSet<Version>>> {
+    let document = lockfile.parse::<toml_edit::DocumentMut>()?;
+    let mut out = BTreeMap::<_, BTreeSet<_>>::new();
+
+    let Some(packages) = document.get("package") else {
+        return Ok(out);
+    };
+    let packages = packages
+        .as_array_of_tables()
+        .ok_or_else(|| ResolveDiffError::InvalidLockfile("`package` list".to_owned()))?;
+
+    for package in packages {
+        let field = |key| {
+            package
+                .get(key)
+                .and_then(toml_edit::Item::as_str)
+                .ok_or_else(|| ResolveDiffError::InvalidLockfile(format!("`{key}` of a package")))
+        };
+        let source = package.get("source").and_then(toml_edit::Item::as_str);
+
+        out.entry(LockedPackage {
+            name: field("name")?.to_owned(),
+            source: source.map(str::to_owned),
+        })
+        .or_default()
+        .insert(field("version")?.parse()?);
+    }
+
+    Ok(out)
+}
+
+impl LockfileDiff {
+    /// The number of added, changed & removed packages
+    pub fn change_count(&self) -> usize {
+        self.added.len() + self.changed.len() + self.removed.len()
+    }
+
+    /// Returns the differences between the contents of two `Cargo.lock`s
+    pub fn between(old: &str, new: &str) -> Result<Self> {
+        let lockfile_format_change =
+            LockfileFormatChange::between(format_version(old)?, format_version(new)?);
+        let old = locked_packages(old)?;
+        let new = locked_packages(new)?;
+
+        let mut diff = LockfileDiff {
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: Vec::new(),
+            lockfile_format_change,
+        };
+
+        for (package, new_versions) in &new {
+            match old.get(package) {
+                None => diff.added.push(LockedVersions {
+                    package: package.clone(),
+                    versions: new_versions.iter().cloned().collect(),
+                }),
+                Some(old_versions) if old_versions != new_versions => {
+                    diff.changed.push(LockedChange {
+                        package: package.clone(),
+                        old_versions: old_versions.iter().cloned().collect(),
+                        new_versions: new_versions.iter().cloned().collect(),
+                    });
+                }
+                Some(_) => (),
+            }
+        }
+
+        diff.removed = old
+            .into_iter()
+            .filter(|(package, _)| !new.contains_key(package))
+            .map(|(package, versions)| LockedVersions {
+                package,
+                versions: versions.into_iter().collect(),
+            })
+            .collect();
+
+        Ok(diff)
+    }
+}
@@ -2,30 +2,80 @@
 
 // NOTE: This doesn't handle `git` dependencies currently, as they cannot really be detected in
 // `cargo metadata` outside of parsing the source.
-use std::collections::{BTreeMap, HashMap};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use camino::Utf8PathBuf;
+use chrono::NaiveDate;
 use clap::Parser;
 use color_eyre::{
     Result,
-    eyre::{Report, bail},
+    eyre::{Report, WrapErr, bail, eyre},
 };
 use crates_io_api::SyncClient;
 use semver::Version;
 use serde::Serialize;
 
-use cargo_resolvediff::Platform;
-use cargo_resolvediff::diff::Diff;
-use cargo_resolvediff::git::Repository;
+use cargo_resolvediff::diff::{Conflict, Diff, DiffSummary, Direction};
+use cargo_resolvediff::git::{HeadGuard, Repository};
+use cargo_resolvediff::lockfile::LockfileDiff;
 use cargo_resolvediff::major_updates::{
-    LatestVersion, ManifestDependencySet, fetch_latest_major_update_for,
+    InconsistentRequirements, LatestVersion, ManifestDependencySet, SkippedDependency,
+    fetch_latest_major_update_for,
 };
-use cargo_resolvediff::resolve::{Resolved, SpecificCrateIdent};
-use cargo_resolvediff::util::{host_platform, locate_project, update};
+use cargo_resolvediff::resolve::{
+    DependencyKind, DependencyKindFilter, LocalPathRedactor, ResolveOptions, Resolved,
+    SpecificCrateIdent,
+};
+use cargo_resolvediff::util::{
+    CargoOptions, FailedUpdate, FeatureSelection, check_packages, configured_build_targets,
+    generate_lockfile, host_platform, installed_platforms, locate_project,
+    supports_update_breaking, update, update_breaking, update_workspace, write_atomically,
+};
+use cargo_resolvediff::{Platform, ResolveDiffError};
+
+/// The line prefixes for added, removed & changed crates in output templates, see `--prefixes`
+#[derive(Serialize)]
+struct Prefixes {
+    added: String,
+    removed: String,
+    changed: String,
+}
+
+impl Prefixes {
+    fn new(color: bool) -> Self {
+        let prefix = |prefix: &str, color_code: &str| {
+            if color {
+                format!("\x1b[{color_code}m{prefix}\x1b[0m ")
+            } else {
+                format!("{prefix} ")
+            }
+        };
+
+        Prefixes {
+            added: prefix("+", "32"),
+            removed: prefix("-", "31"),
+            changed: prefix("~", "33"),
+        }
+    }
+}
 
 struct OutputConfig {
     templated_output: bool,
     templated_in_json: bool,
+    /// Render all output with `deny_output.jinja` instead, see `--format deny`
+    deny_format: bool,
+    prefixes: Option<Prefixes>,
+    /// The name of the template to print the context of instead, see `--print-context`
+    print_context: Option<String>,
+    printed_context: Cell<bool>,
+    /// The `Key: value` trailers appended to all commit messages, see `--commit-trailer`
+    commit_trailers: Vec<String>,
     jinja: minijinja::Environment<'static>,
 }
 
@@ -37,6 +87,8 @@ impl OutputConfig {
     const SQUASHED_COMMIT: &str = "squashed_commit.jinja";
     const SQUASHED_OUTPUT: &str = "squashed_output.jinja";
     const GIT_OUTPUT: &str = "git_output.jinja";
+    const PUBLISHED_OUTPUT: &str = "published_output.jinja";
+    const DENY_OUTPUT: &str = "deny_output.jinja";
 
     const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
         (
@@ -75,15 +127,38 @@ impl OutputConfig {
             Self::GIT_OUTPUT,
             include_str!("default_templates/git_output.jinja"),
         ),
+        (
+            Self::PUBLISHED_OUTPUT,
+            include_str!("default_templates/published_output.jinja"),
+        ),
+        (
+            Self::DENY_OUTPUT,
+            include_str!("default_templates/deny_output.jinja"),
+        ),
     ];
 
     fn init_jinja(
         platforms: &[Platform],
+        host: &Platform,
         path: Option<PathBuf>,
+        use_default_templates: bool,
+        vars: Vec<(String, String)>,
     ) -> Result<minijinja::Environment<'static>> {
         let mut jinja = minijinja::Environment::new();
 
+        jinja.add_global(
+            "vars",
+            minijinja::Value::from_iter(vars.into_iter().collect::<BTreeMap<_, _>>()),
+        );
+
         let short_platform = {
+            // NOTE: Crates run at build time are attributed to the host, even if it isn't one of
+            // the given platforms
+            let mut named = platforms.to_vec();
+            if !named.contains(host) {
+                named.push(host.clone());
+            }
+            let platforms = &named;
             let mapping = platforms
                 .iter()
                 .map(|platform| {
@@ -104,6 +179,18 @@ impl OutputConfig {
 
         jinja.add_filter("short_platform", short_platform);
 
+        let all_platforms = {
+            let all = platforms
+                .iter()
+                .map(|platform| platform.0.clone())
+                .collect::<BTreeSet<_>>();
+            move |platforms: Vec<String>| {
+                all.len() > 1 && platforms.into_iter().collect::<BTreeSet<_>>() == all
+            }
+        };
+
+        jinja.add_test("all_platforms", all_platforms);
+
         if let Some(ref path) = path {
             if !path.is_dir() {
                 bail!("Template directory doesn't exist");
@@ -121,6 +208,10 @@ impl OutputConfig {
                 continue;
             }
 
+            if !use_default_templates && !name.starts_with('_') {
+                continue;
+            }
+
             jinja.add_template(name, template)?;
         }
 
@@ -138,8 +229,28 @@ impl OutputConfig {
             ..ctx
         };
 
+        if self.deny_format {
+            return Ok(self.render(Self::DENY_OUTPUT, ctx)?.into());
+        }
+
+        // NOTE: The prefixes are only part of the context for rendering, not the JSON output
+        let render = |ctx: &minijinja::Value| {
+            self.render(
+                name,
+                minijinja::context! {
+                    prefix => self.prefixes,
+                    ..ctx.clone()
+                },
+            )
+        };
+
+        if !self.templated_output && !self.templated_in_json {
+            // NOTE: `--print-context` should work without rendering output templates as well
+            self.print_context_for(name, &ctx)?;
+        }
+
         if self.templated_in_json {
-            let templated = self.jinja.get_template(name)?.render(&ctx)?;
+            let templated = render(&ctx)?;
             ctx = minijinja::context! {
                 templated => templated,
                 ..ctx
@@ -147,38 +258,83 @@ impl OutputConfig {
         }
 
         if self.templated_output && !self.templated_in_json {
-            Ok(self.jinja.get_template(name)?.render(&ctx)?.into())
+            Ok(render(&ctx)?.into())
         } else {
             Ok(serde_json::to_value(&ctx)?)
         }
     }
 
+    /// Print the context as pretty JSON if the template was selected with `--print-context`
+    fn print_context_for(&self, name: &str, ctx: &minijinja::Value) -> Result<()> {
+        if self.print_context.as_deref() == Some(name) {
+            println!("{}", serde_json::to_string_pretty(ctx)?);
+            self.printed_context.set(true);
+        }
+        Ok(())
+    }
+
+    fn ensure_context_printed(&self) -> Result<()> {
+        match self.print_context {
+            Some(ref name) if !self.printed_context.get() => {
+                bail!("The template `{name}` isn't rendered by this task")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn render(&self, name: &str, ctx: minijinja::Value) -> Result<String> {
+        self.print_context_for(name, &ctx)?;
+        Ok(self.jinja.get_template(name)?.render(ctx)?)
+    }
+
+    /// The context of a diff, with `has_changes` & `change_count` (see [`Diff::change_count`])
+    fn diff_context(diff: &Diff<'_>) -> minijinja::Value {
+        minijinja::context! {
+            has_changes => !diff.is_empty(),
+            change_count => diff.change_count(),
+            ..minijinja::Value::from_serialize(diff),
+        }
+    }
+
+    /// Append the `--commit-trailer`s to a commit message, after a blank line
+    fn with_trailers(&self, message: String) -> String {
+        if self.commit_trailers.is_empty() {
+            return message;
+        }
+        format!(
+            "{}\n\n{}\n",
+            message.trim_end(),
+            self.commit_trailers.join("\n")
+        )
+    }
+
     fn minor_commit(&self, diff: &Diff<'_>) -> Result<String> {
-        Ok(self.jinja.get_template(Self::MINOR_COMMIT)?.render(diff)?)
+        let message = self.render(Self::MINOR_COMMIT, Self::diff_context(diff))?;
+        Ok(self.with_trailers(message))
     }
 
     fn minor_output(&self, diff: &Diff<'_>, commit: Option<&str>) -> Result<serde_json::Value> {
-        self.output(
-            Self::MINOR_OUTPUT,
-            minijinja::Value::from_serialize(diff),
-            commit,
-        )
+        self.output(Self::MINOR_OUTPUT, Self::diff_context(diff), commit)
     }
 
     fn major_context(diff: &Diff<'_>, package: &str, version: &Version) -> minijinja::Value {
+        let breaking = diff
+            .comparison_for(package, version)
+            .and_then(|comparison| comparison.breaking);
         minijinja::context! {
             package => package,
             version => version,
-            ..minijinja::Value::from_serialize(diff),
+            breaking => breaking,
+            ..Self::diff_context(diff),
         }
     }
 
     fn major_commit(&self, diff: &Diff<'_>, package: &str, version: &Version) -> Result<String> {
-        let out = self
-            .jinja
-            .get_template(Self::MAJOR_COMMIT)?
-            .render(Self::major_context(diff, package, version))?;
-        Ok(out)
+        let message = self.render(
+            Self::MAJOR_COMMIT,
+            Self::major_context(diff, package, version),
+        )?;
+        Ok(self.with_trailers(message))
     }
 
     fn major_output(
@@ -195,45 +351,88 @@ impl OutputConfig {
         )
     }
 
-    fn squashed_context(
-        diff: &Diff<'_>,
-        major_updates: &[SpecificCrateIdent],
-        failed_major_updates: &[SpecificCrateIdent],
-    ) -> minijinja::Value {
+    fn squashed_context(diff: &Diff<'_>, updates: &SquashedUpdates) -> minijinja::Value {
         minijinja::context! {
-            major_updates => major_updates,
-            failed_major_updates => failed_major_updates,
-            ..minijinja::Value::from_serialize(diff),
+            ..minijinja::Value::from_serialize(updates),
+            ..Self::diff_context(diff),
         }
     }
 
-    fn squashed_commit(
+    fn squashed_commit(&self, diff: &Diff<'_>, updates: &SquashedUpdates) -> Result<String> {
+        let message = self.render(Self::SQUASHED_COMMIT, Self::squashed_context(diff, updates))?;
+        Ok(self.with_trailers(message))
+    }
+
+    fn squashed_member_commit(
         &self,
         diff: &Diff<'_>,
-        major_updates: &[SpecificCrateIdent],
-        failed_major_updates: &[SpecificCrateIdent],
+        updates: &SquashedUpdates,
+        member: &str,
     ) -> Result<String> {
-        let out =
-            self.jinja
-                .get_template(Self::SQUASHED_COMMIT)?
-                .render(Self::squashed_context(
-                    diff,
-                    major_updates,
-                    failed_major_updates,
-                ))?;
-        Ok(out)
+        let message = self.render(
+            Self::SQUASHED_COMMIT,
+            minijinja::context! {
+                member,
+                ..Self::squashed_context(diff, updates),
+            },
+        )?;
+        Ok(self.with_trailers(message))
+    }
+
+    /// Render the commit templates used by `task` with an empty context, to catch errors in
+    /// (custom) templates before anything gets edited
+    fn check_commit_templates(&self, task: &Task, commit_per_member: bool) -> Result<()> {
+        let diff = Diff::default();
+        let updates = SquashedUpdates::default();
+        let minor = (Self::MINOR_COMMIT, Self::diff_context(&diff));
+
+        let contexts = match task {
+            Task::Minor => vec![minor],
+            Task::Major => vec![
+                minor,
+                (
+                    Self::MAJOR_COMMIT,
+                    Self::major_context(&diff, "example", &Version::new(1, 0, 0)),
+                ),
+            ],
+            Task::Squashed if commit_per_member => vec![(
+                Self::SQUASHED_COMMIT,
+                minijinja::context! {
+                    member => "Cargo.toml",
+                    ..Self::squashed_context(&diff, &updates),
+                },
+            )],
+            Task::Squashed => vec![(
+                Self::SQUASHED_COMMIT,
+                Self::squashed_context(&diff, &updates),
+            )],
+            Task::PrintResolved
+            | Task::PlatformBreakdown
+            | Task::Git { .. }
+            | Task::ThreeWay { .. }
+            | Task::Published { .. } => Vec::new(),
+        };
+
+        for (name, ctx) in contexts {
+            (self
+                .jinja
+                .get_template(name)
+                .and_then(|template| template.render(ctx)))
+            .wrap_err_with(|| format!("Failed to render `{name}` with an empty context"))?;
+        }
+
+        Ok(())
     }
 
     fn squashed_output(
         &self,
         diff: &Diff<'_>,
-        major_updates: &[SpecificCrateIdent],
-        failed_major_updates: &[SpecificCrateIdent],
+        updates: &SquashedUpdates,
         commit: Option<&str>,
     ) -> Result<serde_json::Value> {
         self.output(
             Self::SQUASHED_OUTPUT,
-            Self::squashed_context(diff, major_updates, failed_major_updates),
+            Self::squashed_context(diff, updates),
             commit,
         )
     }
@@ -244,14 +443,40 @@ impl OutputConfig {
             minijinja::context! {
                 from => from,
                 to => to,
-                ..minijinja::Value::from_serialize(diff),
+                ..Self::diff_context(diff),
             },
             Some(to),
         )
     }
 
+    fn published_output(
+        &self,
+        diff: &Diff<'_>,
+        package: &str,
+        version: &Version,
+    ) -> Result<serde_json::Value> {
+        self.output(
+            Self::PUBLISHED_OUTPUT,
+            minijinja::context! {
+                package => package,
+                version => version,
+                ..Self::diff_context(diff),
+            },
+            None,
+        )
+    }
+
+    /// Whether the output is replaced by the context of a template, see `--print-context`
+    fn prints_context(&self) -> bool {
+        self.print_context.is_some()
+    }
+
     fn final_output(&self, value: &serde_json::Value) -> Result<()> {
-        if !self.templated_in_json {
+        if self.prints_context() {
+            return Ok(());
+        }
+
+        if (self.templated_output || self.deny_format) && !self.templated_in_json {
             println!(
                 "{}",
                 value
@@ -266,6 +491,29 @@ impl OutputConfig {
     }
 }
 
+/// Returns the workspace members whose dependencies changed between `old` & `new`, or `None` if
+/// that can't be determined
+fn affected_members(old: &Resolved, new: &Resolved) -> Option<Vec<String>> {
+    let diff = Diff::between(old, new);
+    if !diff.filtered_added.is_empty() || !diff.filtered_removed.is_empty() {
+        return None;
+    }
+
+    let roots = diff.touched_members;
+    if roots.is_empty() {
+        return None;
+    }
+
+    roots
+        .into_iter()
+        .map(|root| {
+            new.member_for_root(&root)
+                .or_else(|| old.member_for_root(&root))
+                .map(str::to_owned)
+        })
+        .collect()
+}
+
 fn output_json(value: &impl Serialize) -> Result<()> {
     use std::io::{self, IsTerminal};
 
@@ -278,40 +526,350 @@ fn output_json(value: &impl Serialize) -> Result<()> {
     Ok(())
 }
 
+/// Serialize an output object with `has_changes` & `change_count` added at the top level
+fn counted_output(output: &impl Serialize, change_count: usize) -> Result<serde_json::Value> {
+    let mut output = serde_json::to_value(output)?;
+    if let Some(object) = output.as_object_mut() {
+        object.insert("has_changes".to_owned(), (change_count > 0).into());
+        object.insert("change_count".to_owned(), change_count.into());
+    }
+    Ok(output)
+}
+
+/// Print a single line of JSON for `--json-lines`, flushing right after
+fn output_json_line(value: &impl Serialize) -> Result<()> {
+    use std::io::{self, Write};
+
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", serde_json::to_string(value)?)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
 /// This program does both minor updates (using `cargo update`) and major updates (by editing the
 /// `Cargo.toml`s in the workspace), and produces review diffs between each step for the dependency
 /// resolution for the given platforms.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(group(clap::ArgGroup::new("major_updates").args(["major", "squashed_major"])))]
+#[command(group(clap::ArgGroup::new("revisions").args(["from", "to"]).multiple(true)))]
+#[command(group(clap::ArgGroup::new("git_revisions").args(["from", "to", "base"]).multiple(true)))]
 struct Args {
     /// The path to the manifest of the workspace to update
     ///
-    /// It is assumed a `Cargo.lock` is present.
+    /// It is assumed a `Cargo.lock` is present, see `--generate-lockfile`.
     #[arg(long)]
     manifest_path: Option<PathBuf>,
+    /// Run `cargo generate-lockfile` if there is no `Cargo.lock` yet
+    #[arg(long)]
+    generate_lockfile: bool,
+    /// Resolve without a committed `Cargo.lock` (for crates that don't commit one), by running
+    /// `cargo metadata` without `--locked`
+    ///
+    /// The results then depend on the current state of the registry, and `cargo` writes the
+    /// `Cargo.lock` it resolved. Since that `Cargo.lock` isn't meant to be committed, this
+    /// conflicts with `--git`, and `cargo metadata` runs for one platform at a time.
+    #[arg(
+        long,
+        conflicts_with_all(["generate_lockfile", "major_updates", "git", "threads"])
+    )]
+    no_lock: bool,
     /// The platform tuples to do dependency resolution for
     ///
-    /// Defaults to only the target tuple of the host if none are given.
+    /// Defaults to the `build.target` configured for `cargo` (via `--cargo-config`,
+    /// `CARGO_BUILD_TARGET` or `.cargo/config.toml` files, in that order) if none are given, or
+    /// only the target tuple of the host otherwise. `all` expands to all targets installed via
+    /// `rustup`.
+    ///
+    /// Crates that run at build time (build scripts, proc-macros & their dependencies) are
+    /// attributed to the host, since `cargo` builds them for it.
     #[arg(short, long)]
     platform: Vec<String>,
+    /// The platform (one of `--platform`) to use as the primary view of the diff
+    ///
+    /// Changes that aren't included for this platform are listed after the others, and marked as
+    /// not being on the base platform.
+    #[arg(long)]
+    base_platform: Option<String>,
     /// Only include resolutions for the platforms given with `--platform` for the main diff
     #[arg(short = 'P', long)]
     filter_to_platforms: bool,
+    /// The kinds of dependency edges to follow during resolution
+    ///
+    /// `dev` only applies to `dev-dependencies` of workspace members, since other
+    /// `dev-dependencies` are never resolved. Proc-macro crates are usually normal dependencies,
+    /// and as such still resolved without `build`.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [EdgeKind::Normal, EdgeKind::Build, EdgeKind::Dev]
+    )]
+    kinds: Vec<EdgeKind>,
+    /// The workspace members (by name or package ID) to start resolution from
+    ///
+    /// Defaults to the default members of the workspace.
+    #[arg(long = "member")]
+    members: Vec<String>,
+    /// Don't track the parents of crates deeper than this in their reasons (with direct
+    /// dependencies at depth `1`), to bound memory usage for very large dependency graphs
+    ///
+    /// This doesn't change which crates are included.
+    #[arg(long, value_name = "DEPTH")]
+    max_reason_depth: Option<usize>,
+    /// Resolve the dependency graph only once for `--platform`s that `cargo metadata` produces
+    /// exactly the same graph for (e.g. two linux variants)
+    ///
+    /// This doesn't change the output, but comparing the graphs has a cost of its own, and every
+    /// distinct graph is kept in memory until all platforms are gathered.
+    #[arg(long)]
+    merge_identical_platforms: bool,
+    /// Omit the crates that were added or removed outside of the platforms given with
+    /// `--platform` from the diff
+    #[arg(long, requires("filter_to_platforms"))]
+    no_filtered: bool,
+    /// Also list the crate versions that didn't change in the diff
+    #[arg(long)]
+    include_unchanged: bool,
+    /// Leave out changes to the crate with this name from the diff
+    ///
+    /// This doesn't prevent updates of this crate, the number of suppressed entries is reported.
+    #[arg(long = "ignore", value_name = "CRATE")]
+    ignored: Vec<String>,
+    /// List a removed crate `OLD` & an added crate `NEW` as `renamed`, instead of separately,
+    /// for crates that are known to replace each other
+    #[arg(long = "rename", value_name = "OLD=NEW", value_parser = parse_rename)]
+    renames: Vec<(String, String)>,
+    /// Only list the changed crates that got downgraded (added & removed crates are still listed)
+    #[arg(long, conflicts_with("only_upgrades"))]
+    only_downgrades: bool,
+    /// Only list the changed crates that got upgraded (added & removed crates are still listed)
+    #[arg(long)]
+    only_upgrades: bool,
+    /// Only list the added, changed & removed crates of the given dependency kinds: `normal`
+    /// crates are included in release builds without being run at build time, `build` crates are
+    /// run at build time & `dev` crates are only included via `dev-dependencies`
+    ///
+    /// Unlike `--kinds`, this doesn't change which dependency edges are resolved, but only filters
+    /// the diff by the kinds of the listed crates. The left out entries are counted in
+    /// `kind_filtered`.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with_all(["summary_only", "diff_lockfile_only"])
+    )]
+    only_kind: Vec<EdgeKind>,
+    /// Add the reasons for inclusions as nested objects (in `structured_reasons`) to the JSON
+    /// output, next to their string form
+    #[arg(long)]
+    structured_reasons: bool,
+    /// Add the `description` & `repository` of added & changed crates to the output
+    #[arg(long)]
+    include_metadata: bool,
+    /// Add the distinct parents that depended on removed crates before (as `dependents`) to the
+    /// output
+    #[arg(long)]
+    include_reverse_deps: bool,
+    /// Additionally list the versions of all added, changed & removed crates grouped by crate
+    /// name (as `by_crate`) in the output
+    ///
+    /// The flat `added`, `changed` & `removed` lists stay as they are.
+    #[arg(long, conflicts_with_all(["summary_only", "diff_lockfile_only"]))]
+    group_by_crate: bool,
+    /// Replace the paths of workspace members & other local crates in the reasons for inclusions
+    /// (and in `touched_members`) with anonymized tokens (`crate-1`, `crate-2`, …), for output
+    /// that gets shared
+    ///
+    /// Every path gets the same token throughout a run, and the workspace root is kept as is.
+    /// This also applies to commit messages.
+    #[arg(
+        long,
+        conflicts_with_all(["check_only_changed", "diff_lockfile_only", "commit_per_member"])
+    )]
+    redact_local_paths: bool,
+    /// Only output the number of `added`, `changed`, `removed` & `ignored` crate versions,
+    /// without collecting the full diff (unless it's needed for a commit message)
+    #[arg(
+        long,
+        conflicts_with_all([
+            "major_updates",
+            "base",
+            "print_resolved",
+            "templated",
+            "templated_in_json",
+            "format",
+            "only_downgrades",
+            "only_upgrades",
+            "renames",
+        ])
+    )]
+    summary_only: bool,
+    /// Fail after the output if any changed crate got a semver-incompatible update (across a
+    /// major version, or a minor version for `0.x`), e.g. to gate minor updates in CI
+    #[arg(long, conflicts_with_all(["major_updates", "summary_only"]))]
+    fail_on_major: bool,
+    /// Add the wall-clock durations of every `cargo metadata` (per platform), `cargo update`,
+    /// `cargo check` & major update to the output, as lists per phase in `timings`
+    #[arg(
+        long,
+        conflicts_with_all(["templated", "format", "print_resolved", "print_context"])
+    )]
+    timings: bool,
+    /// The features to resolve dependencies with, as `all`, `no-default` and/or a comma-separated
+    /// list of features (forwarded to `cargo metadata`)
+    ///
+    /// Defaults to the default features. This is separate from `--check-features`, e.g. to see
+    /// the maximal dependency graph in the diff while checking the updates with default features.
+    #[arg(long, value_name = "FEATURES", value_parser = parse_features)]
+    resolve_features: Option<FeatureSelection>,
+    /// The features to run `cargo check` with for `--check`, see `--resolve-features`
+    #[arg(long, value_name = "FEATURES", value_parser = parse_features, requires("check"))]
+    check_features: Option<FeatureSelection>,
     /// Run `cargo check` for updates
     ///
     /// This may potentially not be desirable since it will run build dependencies.
     #[arg(short = 'c', long)]
     check: bool,
+    /// Only run `cargo check` for the workspace members whose dependencies changed with a major
+    /// update
+    ///
+    /// All default members get checked if the affected members can't be determined.
+    #[arg(long, requires("check"), requires("major_updates"))]
+    check_only_changed: bool,
+    /// Don't run `cargo check` after every major update with `--squashed-major`, but only once
+    /// after all of them
+    ///
+    /// If that check fails, the last major updates are rolled back (and reported as failed) one
+    /// by one until it succeeds. The minor updates are still checked on their own.
+    #[arg(long, requires("check"), requires("squashed_major"))]
+    no_check_on_squashed: bool,
+    /// Do the major updates of `--squashed-major` with `cargo update --breaking` instead of
+    /// looking up new versions on crates.io & editing the manifests here
+    ///
+    /// This requires a nightly `cargo` since 1.81 (detected via `cargo --version`), and falls back
+    /// to the usual major updates otherwise. If the update or `cargo check` fails, all major
+    /// updates are rolled back, and the failure is reported as `cargo_breaking_failure`.
+    #[arg(
+        long,
+        requires("squashed_major"),
+        conflicts_with_all(["no_check_on_squashed", "time_budget"])
+    )]
+    use_cargo_breaking: bool,
+    /// Run all `cargo` commands with `--offline`
+    #[arg(long)]
+    offline: bool,
+    /// Override a `cargo` configuration value (`<KEY>=<VALUE>`, or the path to an extra
+    /// configuration file) for all `cargo` commands, forwarded as `--config`
+    #[arg(long, value_name = "CONFIG")]
+    cargo_config: Vec<String>,
+    /// The `cargo` binary to run for all `cargo` commands (such as a specific toolchain's)
+    #[arg(long, value_name = "PATH", env = "CARGO")]
+    cargo: Option<String>,
+    /// The maximal number of `cargo` processes (such as `cargo metadata` for each platform) to run
+    /// at once
+    ///
+    /// Defaults to the number of available CPUs. Requests to crates.io are always made one at a
+    /// time.
+    #[arg(long, value_name = "N")]
+    threads: Option<NonZeroUsize>,
+    /// The `rustc` binary to run to determine the host platform
+    #[arg(long, value_name = "PATH", env = "RUSTC")]
+    rustc: Option<String>,
     /// Do major updates (this edits `Cargo.toml` files)
+    ///
+    /// Next to the output of every step, the output contains the overall change of the run as
+    /// `net` (with the same context as `git_output.jinja`).
     #[arg(short = 'm', long, requires("git"))]
     major: bool,
     /// Do major updates (this edits `Cargo.toml` files), but don't split minor and major updates
     /// into their own diffs
     #[arg(short = 'M', long, conflicts_with("major"))]
     squashed_major: bool,
+    /// Print the output of every step of `--major` as soon as it completes, as one JSON object per
+    /// line (with the minor updates first & a summary last), instead of a single JSON object
+    ///
+    /// Every line has a `step` key with `"minor"`, `"major"` (with the `package`) or `"summary"`,
+    /// and the `output` of that step (except for the summary).
+    #[arg(long, requires("major"))]
+    json_lines: bool,
+    /// Also write the output of every step of `--major` into its own file in this directory, as
+    /// `minor.json` & `<crate>.json` for each successful major update
+    #[arg(long, requires("major"))]
+    output_per_step: Option<PathBuf>,
+    /// Make one commit per touched member of the workspace with `--squashed-major`, instead of a
+    /// single one
+    ///
+    /// Every commit gets a `Cargo.lock` matching its manifests (adjusted with `cargo update
+    /// --workspace`), and other changed manifests are part of the first commit.
+    #[arg(long, requires("squashed_major"), requires("git"))]
+    commit_per_member: bool,
+    /// Use the contents of this file as the commit message of minor or `--squashed-major`
+    /// updates, instead of rendering `minor_commit.jinja` or `squashed_commit.jinja`
+    ///
+    /// The templated output isn't affected by this.
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires("git"),
+        conflicts_with_all(["major", "commit_per_member"])
+    )]
+    commit_message_file: Option<PathBuf>,
+    /// Append this trailer (such as `Signed-off-by: Name <email>`) to all commit messages, after
+    /// a blank line
+    ///
+    /// Can be given multiple times, the trailers are appended in the given order.
+    #[arg(
+        long = "commit-trailer",
+        value_name = "KEY: VALUE",
+        value_parser = parse_trailer,
+        requires("git")
+    )]
+    commit_trailers: Vec<String>,
+    /// Report the direct dependencies that aren't considered for major updates (such as `git`,
+    /// `path` or other registry dependencies) in the output
+    #[arg(long, requires("major_updates"))]
+    strict: bool,
+    /// Allow major updates to edit the manifests of workspace members outside of the workspace
+    /// root
+    #[arg(long, requires("major_updates"))]
+    allow_external_manifests: bool,
+    /// Copy all `Cargo.toml`s into this directory before editing them for major updates
+    ///
+    /// This keeps the paths relative to the workspace root, and works without `--git`.
+    #[arg(long, requires("major_updates"))]
+    manifest_backup_dir: Option<PathBuf>,
+    /// Only consider versions published on or before this date (`YYYY-MM-DD`, in UTC) for major
+    /// updates, for reproducible results
+    #[arg(long, value_name = "DATE", requires("major_updates"))]
+    as_of: Option<NaiveDate>,
+    /// Skip versions for major updates that require a newer `rust-version` than the packages
+    /// depending on them (following `rust-version.workspace = true`)
+    ///
+    /// This doesn't apply to the updates done by `cargo update --breaking` with
+    /// `--use-cargo-breaking`.
+    #[arg(long, requires("major_updates"))]
+    respect_rust_version: bool,
+    /// Stop attempting further major updates once this much time passed since the start (e.g.
+    /// `90s`, `30m` or `2h`, a bare number is taken as seconds), reporting the remaining crates
+    /// as `deferred_time_budget`
+    ///
+    /// This is only checked between major updates of different crates.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires("major_updates"))]
+    time_budget: Option<Duration>,
+    /// Continue with major updates from the unchanged state if minor updates fail, instead of
+    /// aborting
+    #[arg(long, requires("major_updates"))]
+    continue_on_minor_failure: bool,
     /// Create `git` commits or read a `git` repository
     #[arg(short, long)]
     git: bool,
+    /// Tag the last commit made with `--git` with the given name
+    ///
+    /// Nothing gets tagged if no commit was made.
+    #[arg(long, requires("git"), conflicts_with_all(["from", "to", "base", "print_resolved"]))]
+    tag: Option<String>,
     /// Don't do any updates, but compare from a specific git revision to the current one, or to
     /// `--to`
     #[arg(long, conflicts_with_all(["major", "squashed_major"]), requires("git"))]
@@ -320,15 +878,137 @@ struct Args {
     /// from `--from`
     #[arg(long, conflicts_with_all(["major", "squashed_major"]), requires("git"))]
     to: Option<String>,
+    /// Only compare the packages locked in the `Cargo.lock`s of `--from` & `--to` (by name, source
+    /// & version), without running `cargo metadata` or checking anything out
+    ///
+    /// This is much faster & also works for revisions that don't build, but doesn't consider
+    /// platforms, dependency kinds or reasons.
+    #[arg(
+        long,
+        requires("revisions"),
+        conflicts_with_all([
+            "base",
+            "major_updates",
+            "print_resolved",
+            "templated",
+            "templated_in_json",
+            "format",
+            "summary_only",
+            "fail_on_major",
+        ])
+    )]
+    diff_lockfile_only: bool,
+    /// Don't do any updates, but compare the resolution of the given version of the root package
+    /// as published to the registry (with the `Cargo.lock` it was published with) to the current
+    /// one
+    ///
+    /// This only works for crates that publish their `Cargo.lock` (such as binaries). Instead of
+    /// downloading the `.crate` file directly, the published source is fetched by `cargo metadata`
+    /// for a temporary package depending on exactly `=<VERSION>`, so it uses the registry
+    /// configuration & cache of `cargo` (e.g. with `--offline`).
+    #[arg(
+        long,
+        value_name = "VERSION",
+        conflicts_with_all([
+            "major_updates",
+            "print_resolved",
+            "from",
+            "to",
+            "base",
+            "diff_lockfile_only",
+        ])
+    )]
+    diff_against_published: Option<Version>,
+    /// Compare both `--from` and `--to` against a common base revision, reporting crates that
+    /// changed differently on both sides
+    ///
+    /// Without a value, the base is the merge base of `--from` and `--to`.
+    #[arg(
+        long,
+        num_args(0..=1),
+        conflicts_with_all(["major", "squashed_major"]),
+        requires("git")
+    )]
+    base: Option<Option<String>>,
+    /// Fetch from the given remote (unshallowing shallow clones) before comparing `git`
+    /// revisions with `--from`, `--to` or `--base`
+    ///
+    /// Without a value, this fetches from `origin`.
+    #[arg(
+        long,
+        value_name = "REMOTE",
+        num_args(0..=1),
+        default_missing_value = "origin",
+        requires("git_revisions")
+    )]
+    fetch: Option<String>,
+    /// Stash the local changes before checking out the `git` revisions of `--from`, `--to` or
+    /// `--base`, and pop them again afterwards
+    ///
+    /// Nothing gets popped if there were no local changes to stash. If checking out the original
+    /// branch or commit again fails, the changes are left in the stash.
+    #[arg(long, requires("git_revisions"))]
+    autostash: bool,
+    /// Don't do any updates, but print the resolved dependencies as JSON for debugging purposes
+    #[arg(
+        long,
+        conflicts_with_all(["major_updates", "from", "to", "base", "templated", "templated_in_json"])
+    )]
+    print_resolved: bool,
+    /// Don't do any updates, but print the crates that are only included on a single platform as
+    /// JSON, for each of the `--platform`s
+    ///
+    /// Crates that run at build time count as included on the host platform.
+    #[arg(
+        long,
+        conflicts_with_all([
+            "major_updates",
+            "from",
+            "to",
+            "base",
+            "print_resolved",
+            "templated",
+            "templated_in_json",
+            "format",
+            "print_context",
+            "timings",
+            "tag",
+            "diff_against_published",
+            "diff_lockfile_only",
+        ])
+    )]
+    platform_breakdown: bool,
     /// Produce templated output (or prettified JSON for missing templates)
-    #[arg(short, long, conflicts_with("major"))]
+    #[arg(short, long, conflicts_with_all(["major", "base"]))]
     templated: bool,
     /// Same as `--templated`, but render the templates into strings in a JSON object with more
     /// information
     ///
-    /// This is also compatible with `--major`.
+    /// This is also compatible with `--major` and `--base`.
     #[arg(long, conflicts_with("templated"))]
     templated_in_json: bool,
+    /// Produce output in a different format
+    ///
+    /// `deny` renders new dependencies & dependencies newly run at build time as a `cargo-deny`
+    /// configuration block to review & paste into `deny.toml` (with `deny_output.jinja`).
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with_all(["templated", "templated_in_json", "major", "base", "print_resolved"])
+    )]
+    format: Option<OutputFormat>,
+    /// Prefix added, removed & changed crates with `+`, `-` & `~` in templated output
+    ///
+    /// Commit messages never get prefixed.
+    #[arg(long)]
+    prefixes: bool,
+    /// When to color the prefixes of `--prefixes`
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Also append the output to the JSON array in this file (which gets created if it doesn't
+    /// exist), as an object with the `workspace_root` & the `result`
+    #[arg(long, value_name = "FILE")]
+    merge_into: Option<PathBuf>,
     /// The path to a directory containing minijinja templates
     ///
     /// This option makes sense outside of `--templated`/`--templated-in-json`, because commits
@@ -336,20 +1016,151 @@ struct Args {
     ///
     /// The template names are:
     /// * `minor_commit.jinja`, `major_commit.jinja` and `squashed_commit.jinja` set the commit messages.
-    /// * `minor_output.jinja`, `major_output.jinja`, `squashed_output.jinja` and `git_output.jinja` set the output data for the templated output with `--templated` or `--templated-in-json`.
+    /// * `minor_output.jinja`, `major_output.jinja`, `squashed_output.jinja`, `git_output.jinja` and `published_output.jinja` set the output data for the templated output with `--templated` or `--templated-in-json`.
+    /// * `deny_output.jinja` sets the output for `--format deny`, with the same context as the other output templates.
     ///
     /// The JSON dump for outputs (without `--templated`) is always the same as the context the associated template gets.
     ///
+    /// With `--git`, the commit templates are rendered with an empty diff before any updates, to catch errors early.
+    ///
     /// Extra context per template kind:
-    /// * Output templates receive the commit hash if a new commit was made (via `--git`)
-    /// * `major_commit.jinja` & `major_output.jinja`: `package` & `version` are both strings
-    /// * `squashed_commit.jinja` & `squashed_output.jinja`: `major_updates` & `failed_major_updates` are both lists of objects with the keys `package` & `version`, pointing to strings each
+    /// * All templates receive `vars`, see `--template-var`
+    /// * Output templates receive the commit hash if a new commit was made (via `--git`), and `prefix` with the strings `added`, `removed` & `changed` with `--prefixes` (or `none`)
+    /// * `major_commit.jinja` & `major_output.jinja`: `package` & `version` are both strings, `breaking` is `"StableMajor"`, `"ZeroVerBreaking"` or `none`
+    /// * `squashed_commit.jinja` & `squashed_output.jinja`: `major_updates` & `failed_major_updates` are both lists of objects with the keys `package` & `version`, pointing to strings each, where failed ones also have a `reason` (`"UpdateFailed"` or `"CheckFailed"`) & the `error` output of `cargo`, `minor_failed` is a boolean (see `--continue-on-minor-failure`), `skipped` is a list of objects with the keys `name`, `manifest` & `reason` (see `--strict`), `inconsistent_requirements` is a list of objects with the keys `name`, `manifest` & `requirements` for crates required differently in several tables of a manifest after their major update, `deferred_time_budget` is a list of the crates that weren't attempted (see `--time-budget`), `cargo_breaking_failure` is `none` or an object with a `reason` & the `error` output of `cargo` (see `--use-cargo-breaking`), and `squashed_commit.jinja` gets the `member` manifest path with `--commit-per-member`, with only the major updates mentioned in that manifest
     /// * `git_output.jinja`: `from` & `to` are both strings containing the commit hashes that were part of the comparison
+    /// * `published_output.jinja`: `package` & `version` are both strings for the published crate version of `--diff-against-published`
     ///
     /// Extra functions implemented:
     /// * `short_platform` (filter): Removes the last segment if it remains unique, and all `unknown` segments from platform tuples
+    /// * `all_platforms` (test): Whether a list of platform tuples contains all (of several) platforms given with `--platform`
     #[arg(short = 'T', long, verbatim_doc_comment)]
     template_path: Option<PathBuf>,
+    /// Don't fall back to the default templates for templates missing in `--template-path`,
+    /// failing when they're used instead
+    ///
+    /// The default `_default_templates_body.jinja` & `_default_templates_helpers.jinja` can still
+    /// be included.
+    #[arg(long, requires("template_path"))]
+    no_default_templates: bool,
+    /// Make a value available to all templates (as `vars.<KEY>`), in the form `<KEY>=<VALUE>`
+    #[arg(long = "template-var", value_name = "KEY=VALUE", value_parser = parse_template_var)]
+    template_vars: Vec<(String, String)>,
+    /// Print the context the given template would be rendered with as JSON instead of the
+    /// output, for writing custom templates
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = [
+            "minor_commit",
+            "minor_output",
+            "major_commit",
+            "major_output",
+            "squashed_commit",
+            "squashed_output",
+            "git_output",
+            "published_output",
+            "deny_output",
+        ],
+        conflicts_with_all(["print_resolved", "json_lines"])
+    )]
+    print_context: Option<String>,
+}
+
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let (number, unit) = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((duration, ""), |idx| duration.split_at(idx));
+    let number = number.parse::<u64>().map_err(|_| {
+        format!("expected a duration such as `90s`, `30m` or `2h`, got {duration:?}")
+    })?;
+    let seconds = match unit {
+        "" | "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(60 * 60),
+        _ => {
+            return Err(format!(
+                "unknown duration unit {unit:?}, expected `s`, `m` or `h`"
+            ));
+        }
+    };
+    let seconds = seconds.ok_or_else(|| format!("the duration {duration:?} is too long"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+fn parse_features(features: &str) -> Result<FeatureSelection, String> {
+    let mut selection = FeatureSelection::default();
+    for feature in features.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        match feature {
+            "all" => selection.all_features = true,
+            "no-default" => selection.no_default_features = true,
+            feature => selection.features.push(feature.to_owned()),
+        }
+    }
+    Ok(selection)
+}
+
+fn parse_rename(rename: &str) -> Result<(String, String), String> {
+    match rename.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_owned(), new.to_owned()))
+        }
+        _ => Err(format!("expected `<OLD>=<NEW>`, got {rename:?}")),
+    }
+}
+
+fn parse_template_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("expected `<KEY>=<VALUE>`, got {var:?}")),
+    }
+}
+
+/// Validates a `Key: value` commit trailer, as understood by `git interpret-trailers`
+fn parse_trailer(trailer: &str) -> Result<String, String> {
+    match trailer.split_once(':') {
+        Some((key, value))
+            if !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !value.trim().is_empty()
+                && !value.contains('\n') =>
+        {
+            Ok(format!("{key}: {}", value.trim()))
+        }
+        _ => Err(format!("expected `<KEY>: <VALUE>`, got {trailer:?}")),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+enum EdgeKind {
+    Normal,
+    Build,
+    Dev,
+}
+
+impl EdgeKind {
+    /// Whether a crate of the given kind falls into this category, for `--only-kind`
+    fn matches(self, kind: DependencyKind) -> bool {
+        match self {
+            EdgeKind::Normal => !kind.is_build_active() && kind.is_release_included(),
+            EdgeKind::Build => kind.is_build_active(),
+            EdgeKind::Dev => kind.is_test_only(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+enum OutputFormat {
+    /// A `cargo-deny` configuration block
+    Deny,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+enum ColorChoice {
+    /// Color if the output is a terminal
+    Auto,
+    Always,
+    Never,
 }
 
 #[derive(Clone)]
@@ -357,10 +1168,19 @@ enum Task {
     Minor,
     Major,
     Squashed,
+    PrintResolved,
+    PlatformBreakdown,
     Git {
         from: String,
         to: String,
-        return_to: String,
+    },
+    ThreeWay {
+        base: String,
+        left: String,
+        right: String,
+    },
+    Published {
+        version: Version,
     },
 }
 
@@ -368,9 +1188,49 @@ struct AppContext {
     manifest_path: PathBuf,
     lock_path: PathBuf,
     platforms: Vec<Platform>,
+    base_platform: Option<Platform>,
     include_all_platforms: bool,
+    include_filtered: bool,
+    include_unchanged: bool,
+    ignored: Vec<String>,
+    renames: Vec<(String, String)>,
+    only_direction: Option<Direction>,
+    only_kinds: Vec<EdgeKind>,
+    structured_reasons: bool,
+    include_metadata: bool,
+    include_reverse_deps: bool,
+    group_by_crate: bool,
+    /// See `--redact-local-paths`
+    redactor: Option<RefCell<LocalPathRedactor>>,
+    summary_only: bool,
+    diff_lockfile_only: bool,
+    resolve_options: ResolveOptions,
     check: bool,
+    check_only_changed: bool,
+    no_check_on_squashed: bool,
+    use_cargo_breaking: bool,
+    continue_on_minor_failure: bool,
+    manifest_backup_dir: Option<PathBuf>,
+    allow_external_manifests: bool,
+    as_of: Option<NaiveDate>,
+    respect_rust_version: bool,
+    /// The end of `--time-budget`
+    deadline: Option<Instant>,
+    fail_on_major: bool,
+    /// The semver-incompatible changes found with `--fail-on-major`
+    breaking_changes: Vec<String>,
+    strict: bool,
+    json_lines: bool,
+    output_per_step: Option<PathBuf>,
+    commit_per_member: bool,
+    /// The contents of `--commit-message-file`
+    commit_message: Option<String>,
+    cargo_options: CargoOptions,
     repository: Option<Repository>,
+    /// See `--autostash`
+    autostash: bool,
+    tag: Option<String>,
+    merge_into: Option<PathBuf>,
     output: OutputConfig,
     task: Task,
 }
@@ -379,58 +1239,224 @@ impl TryFrom<Args> for AppContext {
     type Error = Report;
 
     fn try_from(args: Args) -> Result<Self> {
-        let manifest_path = args.manifest_path.map_or_else(locate_project, Ok)?;
+        let cargo_options = CargoOptions {
+            offline: args.offline,
+            config_overrides: args.cargo_config,
+            unlocked: args.no_lock,
+            cargo: args.cargo,
+            rustc: args.rustc,
+            // NOTE: Concurrent `cargo metadata` runs would each write the `Cargo.lock` otherwise
+            threads: if args.no_lock {
+                Some(NonZeroUsize::MIN)
+            } else {
+                args.threads
+            },
+            resolve_features: args.resolve_features.unwrap_or_default(),
+            check_features: args.check_features.unwrap_or_default(),
+            timings: args.timings.then(Arc::default),
+        };
+
+        let manifest_path =
+            (args.manifest_path).map_or_else(|| locate_project(&cargo_options), Ok)?;
         if manifest_path.extension() != Some("toml".as_ref()) {
             bail!("A manifest path should in \".toml\", found {manifest_path:?}");
         }
+        // NOTE: Everything else (the `Cargo.lock`, the `git` repository & the workspace root) is
+        // derived from this, so it shouldn't depend on the working directory
+        let manifest_path = fs::canonicalize(&manifest_path)
+            .wrap_err_with(|| format!("Failed to find the manifest {manifest_path:?}"))?;
 
         let lock_path = manifest_path.with_extension("lock");
+        if !lock_path.is_file() && !args.no_lock {
+            if args.generate_lockfile {
+                generate_lockfile(&manifest_path, &cargo_options)?;
+            } else {
+                bail!(
+                    "No `Cargo.lock` found at {lock_path:?}, run `cargo generate-lockfile` (or pass \
+                    `--generate-lockfile`)"
+                );
+            }
+        }
 
+        let host = host_platform(&cargo_options)?;
         let platforms = if args.platform.is_empty() {
-            vec![host_platform()?]
+            let configured = configured_build_targets(&cargo_options)?;
+            if configured.is_empty() {
+                vec![host.clone()]
+            } else {
+                configured
+            }
         } else {
-            args.platform.into_iter().map(Platform).collect::<Vec<_>>()
+            let mut platforms = Vec::new();
+            for platform in args.platform {
+                let expanded = if platform == "all" {
+                    let installed = installed_platforms()?;
+                    if installed.is_empty() {
+                        bail!("`--platform all` was given, but no targets are installed");
+                    }
+                    installed
+                } else {
+                    vec![Platform(platform)]
+                };
+
+                for platform in expanded {
+                    if !platforms.contains(&platform) {
+                        platforms.push(platform);
+                    }
+                }
+            }
+            platforms
         };
 
-        let mut repository = args.git.then(|| {
-            let repository_path = manifest_path.parent().expect("there was a file name");
-            // We might already be in the directory with the `Cargo.toml`, in which case `git`
-            // commands can run here:
-            let repository_path = (repository_path != "").then(|| repository_path.to_owned());
-            Repository::new(repository_path)
-        });
+        let base_platform = args.base_platform.map(Platform);
+        if let Some(ref base_platform) = base_platform
+            && !platforms.contains(base_platform)
+        {
+            bail!(
+                "The base platform {:?} is not one of the resolved platforms",
+                base_platform.0
+            );
+        }
+
+        let mut repository = args
+            .git
+            .then(|| {
+                let repository_path = manifest_path.parent().expect("there was a file name");
+                Repository::open(Some(repository_path.to_owned()))
+            })
+            .transpose()?;
 
         let output = OutputConfig {
             templated_output: args.templated,
             templated_in_json: args.templated_in_json,
-            jinja: OutputConfig::init_jinja(&platforms, args.template_path)?,
+            deny_format: args.format == Some(OutputFormat::Deny),
+            prefixes: args.prefixes.then(|| {
+                Prefixes::new(match args.color {
+                    ColorChoice::Auto => std::io::IsTerminal::is_terminal(&std::io::stdout()),
+                    ColorChoice::Always => true,
+                    ColorChoice::Never => false,
+                })
+            }),
+            print_context: args.print_context.map(|name| format!("{name}.jinja")),
+            printed_context: Cell::new(false),
+            commit_trailers: args.commit_trailers,
+            jinja: OutputConfig::init_jinja(
+                &platforms,
+                &host,
+                args.template_path,
+                !args.no_default_templates,
+                args.template_vars,
+            )?,
         };
 
         let task = if args.major {
             Task::Major
         } else if args.squashed_major {
             Task::Squashed
-        } else if args.from.is_some() || args.to.is_some() {
+        } else if args.print_resolved {
+            Task::PrintResolved
+        } else if args.platform_breakdown {
+            Task::PlatformBreakdown
+        } else if args.from.is_some() || args.to.is_some() || args.base.is_some() {
             let repository = repository.as_mut().expect("--from & --to require --git");
 
-            let current = repository.current_branch_or_commit()?;
+            if let Some(ref remote) = args.fetch {
+                repository.fetch(remote)?;
+            }
+
+            // NOTE: `HEAD` would be checked out relative to the other side, so it's replaced by
+            // the branch (or the commit ID of the detached `HEAD`) that is checked out now
+            let current = repository.current_branch_or_commit()?.as_str().to_owned();
             let fix = |target: Option<_>| target.filter(|s| s != "HEAD").unwrap_or(current.clone());
-            Task::Git {
-                from: fix(args.from),
-                to: fix(args.to),
-                return_to: current,
+            let from = fix(args.from);
+            let to = fix(args.to);
+            match args.base {
+                None => Task::Git { from, to },
+                Some(base) => Task::ThreeWay {
+                    base: base.map_or_else(|| repository.merge_base(&from, &to), Ok)?,
+                    left: from,
+                    right: to,
+                },
             }
+        } else if let Some(version) = args.diff_against_published {
+            Task::Published { version }
         } else {
             Task::Minor
         };
 
+        if args.git {
+            output.check_commit_templates(&task, args.commit_per_member)?;
+        }
+
+        let commit_message = (args.commit_message_file.as_ref())
+            .map(|path| {
+                fs::read_to_string(path)
+                    .wrap_err_with(|| format!("Failed to read the commit message from {path:?}"))
+            })
+            .transpose()?
+            .map(|message| output.with_trailers(message));
+
         Ok(AppContext {
             manifest_path,
             lock_path,
             platforms,
+            base_platform,
             include_all_platforms: !args.filter_to_platforms,
+            include_filtered: !args.no_filtered,
+            include_unchanged: args.include_unchanged,
+            ignored: args.ignored,
+            renames: args.renames,
+            only_direction: if args.only_downgrades {
+                Some(Direction::Downgrade)
+            } else if args.only_upgrades {
+                Some(Direction::Upgrade)
+            } else {
+                None
+            },
+            only_kinds: args.only_kind,
+            structured_reasons: args.structured_reasons,
+            include_metadata: args.include_metadata,
+            include_reverse_deps: args.include_reverse_deps,
+            group_by_crate: args.group_by_crate,
+            redactor: args.redact_local_paths.then(RefCell::default),
+            summary_only: args.summary_only,
+            diff_lockfile_only: args.diff_lockfile_only,
+            resolve_options: ResolveOptions {
+                kinds: DependencyKindFilter {
+                    normal: args.kinds.contains(&EdgeKind::Normal),
+                    build: args.kinds.contains(&EdgeKind::Build),
+                    dev: args.kinds.contains(&EdgeKind::Dev),
+                },
+                members: args.members,
+                max_reason_depth: args.max_reason_depth,
+                host_platform: Some(host.clone()),
+                merge_identical_platforms: args.merge_identical_platforms,
+            },
             check: args.check,
+            check_only_changed: args.check_only_changed,
+            no_check_on_squashed: args.no_check_on_squashed,
+            use_cargo_breaking: args.use_cargo_breaking,
+            continue_on_minor_failure: args.continue_on_minor_failure,
+            manifest_backup_dir: args.manifest_backup_dir,
+            strict: args.strict,
+            cargo_options,
             repository,
+            autostash: args.autostash,
+            allow_external_manifests: args.allow_external_manifests,
+            as_of: args.as_of,
+            respect_rust_version: args.respect_rust_version,
+            // NOTE: A budget too large to represent as an `Instant` can't run out anyway
+            deadline: args
+                .time_budget
+                .and_then(|budget| Instant::now().checked_add(budget)),
+            fail_on_major: args.fail_on_major,
+            breaking_changes: Vec::new(),
+            json_lines: args.json_lines,
+            output_per_step: args.output_per_step,
+            commit_per_member: args.commit_per_member,
+            commit_message,
+            tag: args.tag,
+            merge_into: args.merge_into,
             output,
             task,
         })
@@ -440,21 +1466,41 @@ impl TryFrom<Args> for AppContext {
 struct MajorUpdateContext {
     manifest_deps: ManifestDependencySet,
     client: SyncClient,
+    /// See `--as-of`
+    as_of: Option<NaiveDate>,
+    /// See `--respect-rust-version`
+    respect_rust_version: bool,
 }
 
 impl MajorUpdateContext {
-    fn new(resolved: &Resolved) -> Result<(Self, Vec<String>)> {
+    fn new(
+        resolved: &Resolved,
+        backup_dir: Option<&Path>,
+        allow_external_manifests: bool,
+        as_of: Option<NaiveDate>,
+        respect_rust_version: bool,
+    ) -> Result<(Self, Vec<String>)> {
         let manifest_deps = ManifestDependencySet::collect(&resolved.full_metadata)?;
+        if !allow_external_manifests {
+            (manifest_deps.manifests)
+                .ensure_within(resolved.full_metadata.workspace_root.as_std_path())
+                .wrap_err("Refusing to edit them without `--allow-external-manifests`")?;
+        }
+        if let Some(backup_dir) = backup_dir {
+            manifest_deps.manifests.back_up_to(backup_dir)?;
+        }
         let direct_dependencies = manifest_deps.dependencies.keys().cloned().collect();
 
         let client = SyncClient::new(
             "cargo-resolvediff (42triangles@tutanota.com)",
-            std::time::Duration::from_millis(1000),
+            Duration::from_millis(1000),
         )?;
 
         let ctx = MajorUpdateContext {
             manifest_deps,
             client,
+            as_of,
+            respect_rust_version,
         };
         Ok((ctx, direct_dependencies))
     }
@@ -466,10 +1512,18 @@ impl MajorUpdateContext {
             .get_mut(&name)
             .expect("Key should have been collected from that map");
 
+        let rust_version = if self.respect_rust_version {
+            self.manifest_deps.manifests.rust_version_for(mentions)?
+        } else {
+            None
+        };
+
         let version = match fetch_latest_major_update_for(
             &self.client,
             &name,
             mentions.iter().map(|mention| mention.version()),
+            self.as_of,
+            rust_version.as_ref(),
         )? {
             LatestVersion::CrateNotFound | LatestVersion::NoMajorUpdates => return Ok(None),
             LatestVersion::NewestUpdate(version) => version,
@@ -500,80 +1554,497 @@ impl MajorUpdateContext {
             .expect("There should have been changes after a major update");
         Ok(commit)
     }
+
+    /// Returns the manifests requiring a crate differently in several tables after its major
+    /// update, warning about each on `stderr`
+    fn inconsistent_requirements(&self, name: &str) -> Vec<InconsistentRequirements> {
+        let inconsistent = self.manifest_deps.inconsistent_requirements(name);
+        for inconsistent in &inconsistent {
+            let requirements = (inconsistent.requirements.iter())
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            eprintln!(
+                "`{name}` is required as {} in different tables of {:?}",
+                requirements.join(", "),
+                inconsistent.manifest
+            );
+        }
+        inconsistent
+    }
+
+    /// Whether the manifest at the given path mentions a given direct dependency
+    fn mentions(&self, manifest: &Path, name: &str) -> bool {
+        self.manifest_deps
+            .dependencies
+            .get(name)
+            .into_iter()
+            .flatten()
+            .any(|mention| self.manifest_deps.manifests.manifest_for(mention).path() == manifest)
+    }
+
+    /// Commit every touched workspace member (see [`Diff::touched_members`]) on its own, with the
+    /// message for each manifest given by `message`, and return the commit IDs
+    ///
+    /// Other changed manifests (such as a virtual root manifest) are part of the first commit.
+    /// Every commit gets a `Cargo.lock` matching its manifests: the manifests of the members that
+    /// aren't committed yet are reset to `HEAD` temporarily, and the final `Cargo.lock` is adjusted
+    /// to them with `cargo update --workspace`.
+    fn git_commit_per_member_after_update(
+        &self,
+        root_manifest: &Path,
+        lock: &Path,
+        touched_members: &BTreeSet<Utf8PathBuf>,
+        repository: &mut Repository,
+        cargo_options: &CargoOptions,
+        mut message: impl FnMut(&Path) -> Result<String>,
+    ) -> Result<Vec<String>> {
+        let root = root_manifest.parent().expect("there was a file name");
+        let manifests = self.manifest_deps.manifests.as_slice();
+        // NOTE: The touched members are relative to the workspace root
+        let (members, others) = (manifests.iter().map(|manifest| manifest.path()))
+            .partition::<Vec<_>, _>(|&path| {
+                (touched_members.iter()).any(|member| root.join(member) == path)
+            });
+
+        for path in others {
+            repository.add(path)?;
+        }
+        // NOTE: Minor updates without any touched members still need a commit for the lock
+        let Some((&last, leading)) = members.split_last() else {
+            repository.add(lock)?;
+            let commit = repository.commit(&message(manifests[0].path())?)?;
+            return Ok(commit.into_iter().collect());
+        };
+
+        let snapshot = FileSnapshot::take(members.iter().copied().chain([lock]))?;
+        let mut commits = Vec::new();
+        for (idx, &member) in leading.iter().enumerate() {
+            let reset_pending = || {
+                for &pending in &members[idx + 1..] {
+                    let relative = pending.strip_prefix(root).unwrap_or(pending);
+                    fs::write(pending, repository.show_file("HEAD", relative)?)?;
+                }
+                update_workspace(root_manifest, cargo_options)
+            };
+            let reset = reset_pending();
+            let staged = reset.and_then(|()| {
+                repository.add(member)?;
+                repository.add(lock)
+            });
+            let restored = snapshot.restore();
+            staged?;
+            restored?;
+            commits.extend(repository.commit(&message(member)?)?);
+        }
+
+        repository.add(last)?;
+        repository.add(lock)?;
+        commits.extend(repository.commit(&message(last)?)?);
+        Ok(commits)
+    }
+}
+
+/// The contents of a set of files, to restore them later
+struct FileSnapshot(Vec<(PathBuf, String)>);
+
+impl FileSnapshot {
+    fn take<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Result<Self> {
+        let files = paths
+            .into_iter()
+            .map(|path| Ok((path.to_owned(), fs::read_to_string(path)?)))
+            .collect::<Result<_>>()?;
+        Ok(FileSnapshot(files))
+    }
+
+    fn restore(&self) -> Result<()> {
+        for (path, contents) in &self.0 {
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// The output of `--diff-lockfile-only`
+#[derive(Serialize)]
+struct LockfileOutput {
+    from: String,
+    to: String,
+    #[serde(flatten)]
+    diff: LockfileDiff,
+}
+
+#[derive(Serialize)]
+struct ThreeWay {
+    base: String,
+    left: serde_json::Value,
+    right: serde_json::Value,
+    conflicts: Vec<Conflict>,
+    /// Whether either side has any changes to the base
+    has_changes: bool,
+    /// The number of changes of both sides together, see [`Diff::change_count`]
+    change_count: usize,
+    /// See `--timings`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<serde_json::Value>,
+}
+
+/// A major update that failed & was rolled back
+#[derive(Serialize)]
+struct FailedMajorUpdate {
+    #[serde(flatten)]
+    ident: SpecificCrateIdent,
+    #[serde(flatten)]
+    failure: FailedUpdate,
+}
+
+/// The extra context for squashed updates
+#[derive(Serialize, Default)]
+struct SquashedUpdates {
+    major_updates: Vec<SpecificCrateIdent>,
+    failed_major_updates: Vec<FailedMajorUpdate>,
+    /// Minor updates failed with `--continue-on-minor-failure`
+    minor_failed: bool,
+    /// Direct dependencies not considered for major updates (only with `--strict`)
+    skipped: Vec<SkippedDependency>,
+    /// Crates that ended up with different requirements in one manifest after a major update
+    inconsistent_requirements: Vec<InconsistentRequirements>,
+    /// Direct dependencies that weren't attempted since `--time-budget` ran out
+    deferred_time_budget: Vec<String>,
+    /// Why `cargo update --breaking` failed with `--use-cargo-breaking`, after which all major
+    /// updates were rolled back
+    cargo_breaking_failure: Option<FailedUpdate>,
+}
+
+/// A line of output for `--json-lines`
+#[derive(Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+enum JsonLine<'a> {
+    /// `None` if minor updates failed with `--continue-on-minor-failure`
+    Minor {
+        output: Option<&'a serde_json::Value>,
+    },
+    Major {
+        package: &'a str,
+        output: &'a serde_json::Value,
+    },
+    Summary {
+        net: &'a serde_json::Value,
+        major_order: &'a [String],
+        failed_major_updates: &'a [FailedMajorUpdate],
+        skipped: &'a [SkippedDependency],
+        inconsistent_requirements: &'a [InconsistentRequirements],
+        deferred_time_budget: &'a [String],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timings: Option<&'a serde_json::Value>,
+    },
 }
 
 #[derive(Serialize)]
 struct MajorUpdates {
-    minor: serde_json::Value,
+    /// The output for the overall change from before the minor updates to after the last major
+    /// update (with the same context as `git_output.jinja`)
+    net: serde_json::Value,
+    /// Whether the overall change has any changes, see [`Diff::is_empty`]
+    has_changes: bool,
+    /// The number of overall changes, see [`Diff::change_count`]
+    change_count: usize,
+    /// `None` if minor updates failed with `--continue-on-minor-failure`
+    minor: Option<serde_json::Value>,
     major_order: Vec<String>,
     major_updates: BTreeMap<String, serde_json::Value>,
-    failed_major_updates: Vec<SpecificCrateIdent>,
+    failed_major_updates: Vec<FailedMajorUpdate>,
+    /// Direct dependencies not considered for major updates (only with `--strict`)
+    skipped: Vec<SkippedDependency>,
+    /// Crates that ended up with different requirements in one manifest after a major update
+    inconsistent_requirements: Vec<InconsistentRequirements>,
+    /// Direct dependencies that weren't attempted since `--time-budget` ran out
+    deferred_time_budget: Vec<String>,
+    /// See `--timings`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<serde_json::Value>,
 }
 
 impl AppContext {
-    fn try_update(&self) -> Result<bool> {
-        update(&self.manifest_path, self.check)
+    /// Whether `--time-budget` ran out
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn try_update(&self) -> Result<Result<(), FailedUpdate>> {
+        update(&self.manifest_path, self.check, &self.cargo_options)
     }
 
-    fn minor_update(&self) -> Result<()> {
-        if !self.try_update()? {
-            bail!("Minor updates failed");
+    /// Try a major update on top of `last`, returning the new resolution if it succeeded
+    ///
+    /// With `--check-only-changed`, `cargo check` only runs for the affected workspace members.
+    fn try_major_update(&self, last: &Resolved) -> Result<Result<Resolved, FailedUpdate>> {
+        if !self.check_only_changed {
+            if let Err(failure) = self.try_update()? {
+                return Ok(Err(failure));
+            }
+            return self.resolve().map(Ok);
         }
 
-        Ok(())
+        if let Err(failure) = update(&self.manifest_path, false, &self.cargo_options)? {
+            return Ok(Err(failure));
+        }
+
+        let resolve = self.resolve()?;
+        let members = affected_members(last, &resolve).unwrap_or_default();
+        if let Err(failure) = check_packages(&self.manifest_path, &members, &self.cargo_options)? {
+            return Ok(Err(failure));
+        }
+
+        Ok(Ok(resolve))
+    }
+
+    /// The dependencies skipped for major updates, if they should be reported with `--strict`
+    fn skipped(&self, major_ctx: &MajorUpdateContext) -> Vec<SkippedDependency> {
+        if self.strict {
+            major_ctx.manifest_deps.skipped.clone()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Run minor updates, returning `false` if they failed with `--continue-on-minor-failure`
+    /// (in which case the `Cargo.lock` is restored)
+    fn minor_update(&self) -> Result<bool> {
+        if !self.continue_on_minor_failure {
+            if self.try_update()?.is_err() {
+                bail!("Minor updates failed");
+            }
+            return Ok(true);
+        }
+
+        let lock_contents = fs::read_to_string(&self.lock_path)?;
+        if self.try_update()?.is_ok() {
+            return Ok(true);
+        }
+
+        eprintln!("Minor updates failed, continuing with major updates from the previous state");
+        fs::write(&self.lock_path, lock_contents)?;
+        Ok(false)
     }
 
     fn resolve(&self) -> Result<Resolved> {
-        Resolved::resolve_from_path(
+        static WARNED_RESOLVER_V1: std::sync::Once = std::sync::Once::new();
+
+        let mut resolved = Resolved::resolve_from_path(
             &self.manifest_path,
             self.platforms.iter().cloned(),
             self.include_all_platforms,
-        )
+            &self.resolve_options,
+            &self.cargo_options,
+        )?;
+        self.redact(&mut resolved);
+
+        if resolved.full_metadata.resolver == "1" {
+            WARNED_RESOLVER_V1.call_once(|| {
+                eprintln!(
+                    "The workspace uses `resolver = \"1\"`, which unifies features across \
+                    platforms & dependency kinds (e.g. build & dev dependencies)"
+                );
+            });
+        }
+
+        Ok(resolved)
+    }
+
+    /// Redact the local paths of a resolution with `--redact-local-paths`
+    fn redact(&self, resolved: &mut Resolved) {
+        if let Some(ref redactor) = self.redactor {
+            resolved.redact_local_paths(&mut redactor.borrow_mut());
+        }
+    }
+
+    fn diff<'a>(&self, old: &'a Resolved, new: &'a Resolved) -> Diff<'a> {
+        let mut diff = Diff::between(old, new);
+        if !self.include_filtered {
+            diff.filtered_added.clear();
+            diff.filtered_removed.clear();
+        }
+        if self.include_unchanged {
+            diff.with_unchanged(old, new);
+        }
+        if !self.ignored.is_empty() {
+            diff.ignore(&self.ignored);
+        }
+        if let Some(direction) = self.only_direction {
+            diff.retain_direction(direction);
+        }
+        if !self.only_kinds.is_empty() {
+            diff.retain_kinds(old, new, |kind| {
+                self.only_kinds.iter().any(|only| only.matches(kind))
+            });
+        }
+        if let Some(ref base_platform) = self.base_platform {
+            diff.with_base_platform(base_platform);
+        }
+        if self.structured_reasons {
+            diff.with_structured_reasons();
+        }
+        if self.include_metadata {
+            diff.with_package_metadata(new);
+        }
+        if self.include_reverse_deps {
+            diff.with_reverse_dependencies(old);
+        }
+        if !self.renames.is_empty() {
+            diff.with_renames(&self.renames);
+        }
+        if self.group_by_crate {
+            diff.with_crate_groups();
+        }
+        diff
     }
 
-    fn minor_update_task(&mut self) -> Result<(Resolved, serde_json::Value)> {
+    /// Remember the semver-incompatible changes of `diff` for `--fail-on-major`
+    fn record_breaking_changes(&mut self, diff: &Diff<'_>) {
+        if !self.fail_on_major {
+            return;
+        }
+
+        let changes = diff.breaking_changes().filter_map(|comparison| {
+            let old = comparison.closest_different_old_version.as_ref()?;
+            Some(format!(
+                "`{} {old}..{}`",
+                comparison.ident.name, comparison.ident.version
+            ))
+        });
+        self.breaking_changes.extend(changes);
+    }
+
+    /// Fail if semver-incompatible changes were found with `--fail-on-major`
+    fn check_breaking_changes(&self) -> Result<()> {
+        if !self.breaking_changes.is_empty() {
+            bail!(
+                "Found semver-incompatible changes: {}",
+                self.breaking_changes.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The output of `--summary-only`, see [`DiffSummary::between`]
+    fn summary(&self, old: &Resolved, new: &Resolved) -> Result<serde_json::Value> {
+        let summary = DiffSummary::between(old, new, &self.ignored);
+        counted_output(&summary, summary.change_count())
+    }
+
+    /// Run minor updates, returning the resolution before them, and the one after them with the
+    /// output unless they failed with `--continue-on-minor-failure`
+    fn minor_update_task(&mut self) -> Result<(Resolved, Option<(Resolved, serde_json::Value)>)> {
         let before = self.resolve()?;
-        self.minor_update()?;
+        if !self.minor_update()? {
+            return Ok((before, None));
+        }
         let after = self.resolve()?;
 
-        let diff = Diff::between(&before, &after);
+        if self.summary_only && self.repository.is_none() {
+            let output = self.summary(&before, &after)?;
+            return Ok((before, Some((after, output))));
+        }
+
+        let diff = self.diff(&before, &after);
+        self.record_breaking_changes(&diff);
 
         let commit = if let Some(ref mut repo) = self.repository {
+            let message = match self.commit_message {
+                Some(ref message) => message.clone(),
+                None => self.output.minor_commit(&diff)?,
+            };
             repo.add(&self.lock_path)?;
-            repo.commit(&self.output.minor_commit(&diff)?)?
+            repo.commit(&message)?
         } else {
             None
         };
 
-        let output = self.output.minor_output(&diff, commit.as_deref())?;
-        Ok((after, output))
+        let output = if self.summary_only {
+            self.summary(&before, &after)?
+        } else {
+            self.output.minor_output(&diff, commit.as_deref())?
+        };
+        Ok((before, Some((after, output))))
+    }
+
+    /// Write the output of a single step to `<name>.json` in the directory of `--output-per-step`
+    fn write_step_output(&self, name: &str, output: &serde_json::Value) -> Result<()> {
+        if let Some(ref dir) = self.output_per_step {
+            std::fs::create_dir_all(dir)?;
+            let path = dir.join(format!("{name}.json"));
+            std::fs::write(path, serde_json::to_string_pretty(output)?)?;
+        }
+        Ok(())
     }
 
     fn major_update_task(&mut self) -> Result<MajorUpdates> {
-        let (mut last, minor) = self.minor_update_task()?;
+        let baseline_commit = (self.repository.as_ref())
+            .expect("Split major updates require a git repository")
+            .current_commit()?;
+
+        let (baseline, minor) = self.minor_update_task()?;
+        let (mut last, minor) = match minor {
+            Some((after, output)) => (after, Some(output)),
+            // NOTE: Failed minor updates were rolled back, so this is the same as the baseline
+            None => (self.resolve()?, None),
+        };
+        if let Some(ref minor) = minor {
+            self.write_step_output("minor", minor)?;
+        }
+        if self.json_lines {
+            output_json_line(&JsonLine::Minor {
+                output: minor.as_ref(),
+            })?;
+        }
 
-        let (mut major_ctx, direct_dependencies) = MajorUpdateContext::new(&last)?;
+        let (mut major_ctx, direct_dependencies) = MajorUpdateContext::new(
+            &last,
+            self.manifest_backup_dir.as_deref(),
+            self.allow_external_manifests,
+            self.as_of,
+            self.respect_rust_version,
+        )?;
 
         let mut major_order = Vec::new();
         let mut major_updates = BTreeMap::new();
         let mut failed_major_updates = Vec::new();
+        let mut inconsistent_requirements = Vec::new();
+        let mut deferred_time_budget = Vec::new();
 
         major_ctx.manifest_deps.commit()?;
 
         for package in direct_dependencies {
+            if self.out_of_time() {
+                deferred_time_budget.push(package);
+                continue;
+            }
+            let _timing = self
+                .cargo_options
+                .time("major_update", || Some(package.clone()));
+
             major_ctx.manifest_deps.roll_back()?;
 
             let Some(package) = major_ctx.update_for(package)? else {
                 continue;
             };
 
-            if !self.try_update()? {
-                failed_major_updates.push(package);
-                continue;
+            let resolve = match self.try_major_update(&last)? {
+                Ok(resolve) => resolve,
+                Err(failure) => {
+                    // NOTE: This also restores the `Cargo.lock`, which `cargo update` already
+                    // changed if only `cargo check` failed:
+                    major_ctx.manifest_deps.roll_back()?;
+                    failed_major_updates.push(FailedMajorUpdate {
+                        ident: package,
+                        failure,
+                    });
+                    continue;
+                }
             };
 
-            let resolve = self.resolve()?;
-            let diff = Diff::between(&last, &resolve);
+            let diff = self.diff(&last, &resolve);
 
             let message = self
                 .output
@@ -591,6 +2062,14 @@ impl AppContext {
                     .major_output(&diff, &package.name, &package.version, Some(&commit))?;
 
             major_ctx.manifest_deps.commit()?;
+            inconsistent_requirements.extend(major_ctx.inconsistent_requirements(&package.name));
+            self.write_step_output(&package.name, &output)?;
+            if self.json_lines {
+                output_json_line(&JsonLine::Major {
+                    package: &package.name,
+                    output: &output,
+                })?;
+            }
 
             major_order.push(package.name.clone());
             major_updates.insert(package.name, output);
@@ -598,88 +2077,445 @@ impl AppContext {
             last = resolve;
         }
 
+        let final_commit = (self.repository.as_ref())
+            .expect("Split major updates require a git repository")
+            .current_commit()?;
+        let net_diff = self.diff(&baseline, &last);
+        let net = (self.output).git_output(&net_diff, &baseline_commit, &final_commit)?;
+
         Ok(MajorUpdates {
+            net,
+            has_changes: !net_diff.is_empty(),
+            change_count: net_diff.change_count(),
             minor,
             major_order,
             major_updates,
             failed_major_updates,
+            skipped: self.skipped(&major_ctx),
+            inconsistent_requirements,
+            deferred_time_budget,
+            timings: self.timings()?,
         })
     }
 
     fn squashed_update_task(&mut self) -> Result<serde_json::Value> {
         let before = self.resolve()?;
 
-        self.minor_update()?;
+        let mut updates = SquashedUpdates {
+            minor_failed: !self.minor_update()?,
+            ..SquashedUpdates::default()
+        };
 
-        let (mut major_ctx, direct_dependencies) = MajorUpdateContext::new(&before)?;
+        let (mut major_ctx, direct_dependencies) = MajorUpdateContext::new(
+            &before,
+            self.manifest_backup_dir.as_deref(),
+            self.allow_external_manifests,
+            self.as_of,
+            self.respect_rust_version,
+        )?;
 
-        let mut major_updates = Vec::new();
-        let mut failed_major_updates = Vec::new();
+        // NOTE: Only `--check-only-changed` requires the resolution after each step
+        let mut last = if self.check_only_changed && !self.no_check_on_squashed {
+            Some(self.resolve()?)
+        } else {
+            None
+        };
+        // NOTE: With `--no-check-on-squashed`, the state before every successful major update is
+        // kept to be able to roll it back after the final check
+        let mut snapshots = Vec::new();
 
         major_ctx.manifest_deps.commit()?;
+        // NOTE: `cargo update --breaking` does all major updates at once
+        let direct_dependencies = if self.use_cargo_breaking && self.cargo_breaking_supported()? {
+            self.cargo_breaking_update(&before, &mut major_ctx, &mut updates)?;
+            Vec::new()
+        } else {
+            direct_dependencies
+        };
         for package in direct_dependencies {
+            if self.out_of_time() {
+                updates.deferred_time_budget.push(package);
+                continue;
+            }
+            let _timing = self
+                .cargo_options
+                .time("major_update", || Some(package.clone()));
+
             major_ctx.manifest_deps.roll_back()?;
 
+            let snapshot = if self.no_check_on_squashed {
+                Some(FileSnapshot::take(
+                    (major_ctx.manifest_deps.manifests.as_slice().iter())
+                        .map(|manifest| manifest.path())
+                        .chain([self.lock_path.as_path()]),
+                )?)
+            } else {
+                None
+            };
+
             let Some(package) = major_ctx.update_for(package)? else {
                 continue;
             };
 
-            if !self.try_update()? {
-                failed_major_updates.push(package);
+            let result = match last {
+                Some(ref mut last) => self.try_major_update(last)?.map(|resolve| *last = resolve),
+                None if self.no_check_on_squashed => {
+                    update(&self.manifest_path, false, &self.cargo_options)?
+                }
+                None => self.try_update()?,
+            };
+
+            if let Err(failure) = result {
+                // NOTE: This also restores the `Cargo.lock`, which `cargo update` already changed
+                // if only `cargo check` failed:
+                major_ctx.manifest_deps.roll_back()?;
+                updates.failed_major_updates.push(FailedMajorUpdate {
+                    ident: package,
+                    failure,
+                });
                 continue;
             };
 
             major_ctx.manifest_deps.commit()?;
-            major_updates.push(package);
+            (updates.inconsistent_requirements)
+                .extend(major_ctx.inconsistent_requirements(&package.name));
+            updates.major_updates.push(package);
+            snapshots.extend(snapshot);
         }
 
+        if self.no_check_on_squashed {
+            self.check_squashed(&before, &mut updates, snapshots)?;
+        }
+
+        updates.skipped = self.skipped(&major_ctx);
+
         let after = self.resolve()?;
-        let diff = Diff::between(&before, &after);
+        let diff = self.diff(&before, &after);
+
+        let commit = match self.repository {
+            Some(ref mut repository) if self.commit_per_member => {
+                let root = self.manifest_path.parent().expect("there was a file name");
+                let commits = major_ctx.git_commit_per_member_after_update(
+                    &self.manifest_path,
+                    &self.lock_path,
+                    &diff.touched_members,
+                    repository,
+                    &self.cargo_options,
+                    |manifest| {
+                        let member_updates = SquashedUpdates {
+                            major_updates: (updates.major_updates.iter())
+                                .filter(|package| major_ctx.mentions(manifest, &package.name))
+                                .cloned()
+                                .collect(),
+                            minor_failed: updates.minor_failed,
+                            ..SquashedUpdates::default()
+                        };
+                        let member = manifest.strip_prefix(root).unwrap_or(manifest);
+                        self.output.squashed_member_commit(
+                            &diff,
+                            &member_updates,
+                            &member.to_string_lossy(),
+                        )
+                    },
+                )?;
+                commits.last().cloned()
+            }
+            Some(ref mut repository) => {
+                let message = match self.commit_message {
+                    Some(ref message) => message.clone(),
+                    None => self.output.squashed_commit(&diff, &updates)?,
+                };
+                Some(major_ctx.git_commit_after_update(&self.lock_path, repository, &message)?)
+            }
+            None => None,
+        };
 
-        let message = self
+        let output = self
             .output
-            .squashed_commit(&diff, &major_updates, &failed_major_updates)?;
+            .squashed_output(&diff, &updates, commit.as_deref())?;
+        Ok(output)
+    }
 
-        let commit = self
-            .repository
-            .as_mut()
-            .map(|repository| {
-                major_ctx.git_commit_after_update(&self.lock_path, repository, &message)
+    /// Whether `--use-cargo-breaking` can be used, warning about falling back to the usual major
+    /// updates otherwise
+    fn cargo_breaking_supported(&self) -> Result<bool> {
+        let supported = supports_update_breaking(&self.cargo_options)?;
+        if !supported {
+            eprintln!(
+                "`cargo` doesn't support `cargo update --breaking`, doing the major updates without it"
+            );
+        }
+        Ok(supported)
+    }
+
+    /// Do all squashed major updates at once with `cargo update --breaking` for
+    /// `--use-cargo-breaking`, recording the semver-incompatible updates of direct dependencies
+    ///
+    /// If the update or `cargo check` fails, the manifests & the `Cargo.lock` are rolled back, and
+    /// the failure is recorded in the `updates`.
+    fn cargo_breaking_update(
+        &self,
+        before: &Resolved,
+        major_ctx: &mut MajorUpdateContext,
+        updates: &mut SquashedUpdates,
+    ) -> Result<()> {
+        let timing = self.cargo_options.time("major_update", || None);
+        let result = update_breaking(&self.manifest_path, self.check, &self.cargo_options)?;
+        drop(timing);
+        if let Err(failure) = result {
+            major_ctx.manifest_deps.roll_back()?;
+            updates.cargo_breaking_failure = Some(failure);
+            return Ok(());
+        }
+
+        let after = self.resolve()?;
+        let diff = Diff::between(before, &after);
+        updates.major_updates = diff
+            .breaking_changes()
+            .filter(|comparison| {
+                (major_ctx.manifest_deps.dependencies).contains_key(&comparison.ident.name)
             })
-            .transpose()?;
+            .map(|comparison| comparison.ident.clone())
+            .collect();
+        Ok(())
+    }
 
-        let output = self.output.squashed_output(
-            &diff,
-            &major_updates,
-            &failed_major_updates,
-            commit.as_deref(),
-        )?;
-        Ok(output)
+    /// Run `cargo check` once after all squashed major updates for `--no-check-on-squashed`,
+    /// rolling back the last major updates (restoring the `snapshots` from before them) until it
+    /// succeeds
+    ///
+    /// The in-memory state of the manifests isn't rolled back, so only the files are up to date
+    /// afterwards.
+    fn check_squashed(
+        &self,
+        before: &Resolved,
+        updates: &mut SquashedUpdates,
+        mut snapshots: Vec<FileSnapshot>,
+    ) -> Result<()> {
+        loop {
+            let members = if self.check_only_changed {
+                affected_members(before, &self.resolve()?).unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            let Err(failure) = check_packages(&self.manifest_path, &members, &self.cargo_options)?
+            else {
+                return Ok(());
+            };
+
+            let (Some(snapshot), Some(package)) = (snapshots.pop(), updates.major_updates.pop())
+            else {
+                bail!("`cargo check` failed without any major updates left to roll back");
+            };
+            eprintln!(
+                "`cargo check` failed after the squashed major updates, rolling back `{}`",
+                package.name,
+            );
+            snapshot.restore()?;
+
+            (updates.inconsistent_requirements)
+                .retain(|requirements| requirements.name != package.name);
+            updates.failed_major_updates.push(FailedMajorUpdate {
+                ident: package,
+                failure,
+            });
+        }
+    }
+
+    /// The durations recorded so far with `--timings`
+    fn timings(&self) -> Result<Option<serde_json::Value>> {
+        let timings = self.cargo_options.timings.as_deref();
+        Ok(timings.map(serde_json::to_value).transpose()?)
     }
 
-    fn git_task(&mut self, from: &str, to: &str, return_to: &str) -> Result<serde_json::Value> {
+    /// Add the `timings` to a JSON object output for `--timings`
+    fn add_timings(&self, output: &mut serde_json::Value) -> Result<()> {
+        if let Some(timings) = self.timings()?
+            && let Some(output) = output.as_object_mut()
+        {
+            output.insert("timings".to_owned(), timings);
+        }
+        Ok(())
+    }
+
+    /// Append the output to the JSON array in the file of `--merge-into`, if any
+    fn merge_output(&self, output: &impl Serialize) -> Result<()> {
+        let Some(ref path) = self.merge_into else {
+            return Ok(());
+        };
+
+        let mut merged = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str::<Vec<serde_json::Value>>(&contents)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error.into()),
+        };
+        merged.push(serde_json::json!({
+            "workspace_root": self.manifest_path.parent(),
+            "result": output,
+        }));
+
+        write_atomically(path, &serde_json::to_string_pretty(&merged)?)
+    }
+
+    /// Tag the last commit made with `--tag`, if any
+    fn tag_last_commit(&self) -> Result<()> {
+        if let Some(ref tag) = self.tag
+            && let Some(ref repository) = self.repository
+            && repository.last_commit().is_some()
+        {
+            repository.tag(tag, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check out `target` (relative to the originally checked out branch or commit) and resolve
+    /// it, returning the commit ID along with the resolution
+    fn resolve_at(&self, head: &mut HeadGuard<'_>, target: &str) -> Result<(String, Resolved)> {
+        head.return_to_target()?;
+        head.checkout(target)?;
+        let commit = head.current_commit()?;
+        Ok((commit, self.resolve()?))
+    }
+
+    /// Resolve each of the given targets, checking out the original branch or commit again
+    /// afterwards (even if resolution fails)
+    ///
+    /// With `--autostash`, the local changes are stashed before & popped after that.
+    fn resolve_all_at<const N: usize>(
+        &mut self,
+        targets: [&str; N],
+    ) -> Result<[(String, Resolved); N]> {
         let mut repository = self
             .repository
             .take()
             .expect("git comparisons require a repository");
 
-        repository.checkout(from)?;
-        let from_commit = repository.current_commit()?;
-        let from = self.resolve()?;
+        let stashed = self.autostash && repository.stash_push()?;
+        let mut head = repository.pin_head()?;
+        let resolved = itertools::process_results(
+            targets
+                .into_iter()
+                .map(|target| self.resolve_at(&mut head, target)),
+            |iter| iter.collect::<Vec<_>>(),
+        );
+        // NOTE: The stash only gets popped onto the original `HEAD`, so the local changes stay in
+        // the stash if restoring it fails
+        let mut errors = Vec::new();
+        match head.restore() {
+            Ok(()) => errors.extend(repository.stash_pop(stashed).err()),
+            Err(err) => {
+                errors.push(err);
+                if stashed {
+                    errors.push(eyre!(
+                        "The local changes stashed by `--autostash` are still in the stash"
+                    ));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            errors.extend(resolved.err());
+            return Err(ResolveDiffError::RollbackFailed(errors).into());
+        }
 
-        repository.checkout(return_to)?;
-        repository.checkout(to)?;
-        let to_commit = repository.current_commit()?;
-        let to = self.resolve()?;
+        self.repository = Some(repository);
+        Ok(resolved?
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("one resolution per target")))
+    }
 
-        repository.checkout(return_to)?;
+    fn git_task(&mut self, from: &str, to: &str) -> Result<serde_json::Value> {
+        if self.diff_lockfile_only {
+            return self.lockfile_task(from, to);
+        }
 
-        self.repository = Some(repository);
-        let output =
-            self.output
-                .git_output(&Diff::between(&from, &to), &from_commit, &to_commit)?;
+        let [(from_commit, from), (to_commit, to)] = self.resolve_all_at([from, to])?;
+
+        if self.summary_only {
+            return self.summary(&from, &to);
+        }
+
+        let diff = self.diff(&from, &to);
+        self.record_breaking_changes(&diff);
+
+        let output = self.output.git_output(&diff, &from_commit, &to_commit)?;
         Ok(output)
     }
+
+    /// Compare the `Cargo.lock`s at two revisions for `--diff-lockfile-only`
+    fn lockfile_task(&self, from: &str, to: &str) -> Result<serde_json::Value> {
+        let repository = (self.repository.as_ref()).expect("git comparisons require a repository");
+        let lock_file_name = self.lock_path.file_name().expect("there was a file name");
+        let lockfile_at = |rev| {
+            repository
+                .show_file(rev, lock_file_name.as_ref())
+                .wrap_err_with(|| format!("Failed to read the `Cargo.lock` at {rev:?}"))
+        };
+
+        let diff = LockfileDiff::between(&lockfile_at(from)?, &lockfile_at(to)?)?;
+        let change_count = diff.change_count();
+        let output = LockfileOutput {
+            from: repository.commit_of(from)?,
+            to: repository.commit_of(to)?,
+            diff,
+        };
+        counted_output(&output, change_count)
+    }
+
+    /// Compare the resolution of the published `version` of the root package to the current one
+    fn published_task(&mut self, version: &Version) -> Result<serde_json::Value> {
+        let current = self.resolve()?;
+        let Some(package) = current.full_metadata.root_package() else {
+            bail!("`--diff-against-published` requires a root package, not a virtual workspace");
+        };
+        let package = package.name.to_string();
+
+        let mut published = Resolved::resolve_published(
+            &package,
+            version,
+            self.platforms.iter().cloned(),
+            self.include_all_platforms,
+            &self.resolve_options,
+            &self.cargo_options,
+        )
+        .wrap_err_with(|| format!("Failed to resolve the published `{package} {version}`"))?;
+        self.redact(&mut published);
+
+        if self.summary_only {
+            return self.summary(&published, &current);
+        }
+
+        let diff = self.diff(&published, &current);
+        self.record_breaking_changes(&diff);
+
+        self.output.published_output(&diff, &package, version)
+    }
+
+    fn three_way_task(&mut self, base: &str, left: &str, right: &str) -> Result<ThreeWay> {
+        let [
+            (base_commit, base),
+            (left_commit, left),
+            (right_commit, right),
+        ] = self.resolve_all_at([base, left, right])?;
+
+        let left_diff = self.diff(&base, &left);
+        self.record_breaking_changes(&left_diff);
+        let left_output = (self.output).git_output(&left_diff, &base_commit, &left_commit)?;
+
+        let right_diff = self.diff(&base, &right);
+        self.record_breaking_changes(&right_diff);
+        let right_output = (self.output).git_output(&right_diff, &base_commit, &right_commit)?;
+
+        Ok(ThreeWay {
+            conflicts: Conflict::between(&base, &left, &right),
+            has_changes: !left_diff.is_empty() || !right_diff.is_empty(),
+            change_count: left_diff.change_count() + right_diff.change_count(),
+            base: base_commit,
+            left: left_output,
+            right: right_output,
+            timings: self.timings()?,
+        })
+    }
 }
 
 fn main() -> Result<()> {
@@ -687,22 +2523,72 @@ fn main() -> Result<()> {
 
     let mut ctx = AppContext::try_from(Args::parse())?;
 
-    let out = match ctx.task.clone() {
-        Task::Minor => ctx.minor_update_task()?.1,
+    let mut out = match ctx.task.clone() {
+        Task::Minor => {
+            let out = ctx
+                .minor_update_task()?
+                .1
+                .expect("Minor updates can only fail without aborting for major updates")
+                .1;
+            ctx.tag_last_commit()?;
+            out
+        }
         Task::Major => {
             let out = ctx.major_update_task()?;
+            ctx.tag_last_commit()?;
+            if ctx.json_lines {
+                output_json_line(&JsonLine::Summary {
+                    net: &out.net,
+                    major_order: &out.major_order,
+                    failed_major_updates: &out.failed_major_updates,
+                    skipped: &out.skipped,
+                    inconsistent_requirements: &out.inconsistent_requirements,
+                    deferred_time_budget: &out.deferred_time_budget,
+                    timings: out.timings.as_ref(),
+                })?;
+            } else if !ctx.output.prints_context() {
+                output_json(&out)?;
+            }
+            ctx.merge_output(&out)?;
+            ctx.output.ensure_context_printed()?;
+            return Ok(());
+        }
+        Task::Squashed => {
+            let out = ctx.squashed_update_task()?;
+            ctx.tag_last_commit()?;
+            out
+        }
+        Task::PrintResolved => {
+            let out = ctx.resolve()?;
             output_json(&out)?;
+            ctx.merge_output(&out)?;
+            return Ok(());
+        }
+        Task::PlatformBreakdown => {
+            let out = ctx.resolve()?.platform_breakdown();
+            output_json(&out)?;
+            ctx.merge_output(&out)?;
+            return Ok(());
+        }
+        Task::Git { from, to } => ctx.git_task(&from, &to)?,
+        Task::Published { version } => ctx.published_task(&version)?,
+        Task::ThreeWay { base, left, right } => {
+            let out = ctx.three_way_task(&base, &left, &right)?;
+            if !ctx.output.prints_context() {
+                output_json(&out)?;
+            }
+            ctx.merge_output(&out)?;
+            ctx.output.ensure_context_printed()?;
+            ctx.check_breaking_changes()?;
             return Ok(());
         }
-        Task::Squashed => ctx.squashed_update_task()?,
-        Task::Git {
-            from,
-            to,
-            return_to,
-        } => ctx.git_task(&from, &to, &return_to)?,
     };
 
+    ctx.add_timings(&mut out)?;
     ctx.output.final_output(&out)?;
+    ctx.merge_output(&out)?;
+    ctx.output.ensure_context_printed()?;
+    ctx.check_breaking_changes()?;
 
     Ok(())
 }
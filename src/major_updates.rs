@@ -3,16 +3,33 @@
 //! Handle major updates & related tasks
 
 use crate::{
+    ResolveDiffError,
     indexed::IndexedMetadata,
     toml_edit::{MutableTomlFile, TomlPathLookup},
 };
-use color_eyre::{Result, eyre::eyre};
+use chrono::NaiveDate;
+use color_eyre::{Report, Result};
 use crates_io_api::SyncClient;
 use itertools::Itertools;
 use semver::{Version, VersionReq};
-use std::{borrow::Borrow, collections::BTreeMap, fs, iter, path::PathBuf};
+use serde::Serialize;
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    fs, iter,
+    path::{Path, PathBuf},
+};
 use tinyvec::{ArrayVec, array_vec};
 
+/// A [`ResolveDiffError::InvalidManifest`] for a given manifest
+fn invalid_manifest(manifest: &MutableTomlFile, message: impl Into<String>) -> Report {
+    ResolveDiffError::InvalidManifest {
+        path: manifest.path().to_owned(),
+        message: message.into(),
+    }
+    .into()
+}
+
 /// Check whether a [`Version`] is considered a major update for a given [`VersionReq`].
 ///
 /// Major updates are defined as:
@@ -69,11 +86,58 @@ pub fn is_major_update_for(requirement: &VersionReq, version: &Version) -> bool
     true
 }
 
+/// The kind of a semver-incompatible change between two versions of a crate
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum BreakingChange {
+    /// The major version changed (e.g. `1.2` to `2.0`, or `0.9` to `1.0`)
+    StableMajor,
+    /// The major version is `0` and the minor version changed (e.g. `0.3` to `0.4`), or both the
+    /// major & minor versions are `0` and the patch version changed (e.g. `0.0.1` to `0.0.2`)
+    ZeroVerBreaking,
+}
+
+impl BreakingChange {
+    /// Classify the change between two versions, returning `None` if they are semver-compatible
+    /// according to `cargo`
+    pub fn between(old: &Version, new: &Version) -> Option<Self> {
+        if old.major != new.major {
+            Some(BreakingChange::StableMajor)
+        } else if old.major == 0
+            && (old.minor != new.minor || (old.minor == 0 && old.patch != new.patch))
+        {
+            Some(BreakingChange::ZeroVerBreaking)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a `rust-version` value, treating missing minor & patch versions as `0` (so `1.70` is
+/// `1.70.0`)
+fn parse_rust_version(rust_version: &str) -> Option<Version> {
+    let mut parts = rust_version.trim().split('.').map(str::parse::<u64>);
+    let mut next = || parts.next().transpose().ok();
+    let major = next()??;
+    let minor = next()?.unwrap_or(0);
+    let patch = next()?.unwrap_or(0);
+    if next()?.is_some() {
+        return None;
+    }
+    Some(Version::new(major, minor, patch))
+}
+
 /// Fetch all versions for a crate that have not been yanked.
+///
+/// With `as_of`, only versions published on or before that date (in UTC) are returned, to get
+/// reproducible results. With `rust_version`, versions requiring a newer `rust-version` are
+/// skipped (versions without or with an unparsable `rust-version` are kept).
 pub fn fetch_versions_for(
     client: &SyncClient,
     package: &str,
+    as_of: Option<NaiveDate>,
+    rust_version: Option<&Version>,
 ) -> Result<Option<impl Iterator<Item = Version>>> {
+    let rust_version = rust_version.cloned();
     let info = match client.get_crate(package) {
         Ok(info) => info,
         Err(crates_io_api::Error::NotFound(_)) => return Ok(None),
@@ -83,6 +147,14 @@ pub fn fetch_versions_for(
         .versions
         .into_iter()
         .filter(|version| !version.yanked)
+        .filter(move |version| as_of.is_none_or(|date| version.created_at.date_naive() <= date))
+        .filter(move |version| {
+            let Some(max) = &rust_version else {
+                return true;
+            };
+            let required = version.rust_version.as_deref().and_then(parse_rust_version);
+            required.is_none_or(|required| required <= *max)
+        })
         .map(|version| {
             version
                 .num
@@ -93,13 +165,16 @@ pub fn fetch_versions_for(
 }
 
 /// Fetch all versions of a crate that are considered major updates for _any_ of the given
-/// [`VersionReq`]s and have not been yanked
+/// [`VersionReq`]s and have not been yanked (see [`fetch_versions_for`] for `as_of` &
+/// `rust_version`)
 pub fn fetch_major_updates_for(
     client: &SyncClient,
     package: &str,
     reqs: impl Iterator<Item: Borrow<VersionReq>> + Clone,
+    as_of: Option<NaiveDate>,
+    rust_version: Option<&Version>,
 ) -> Result<Option<impl Iterator<Item = Version>>> {
-    let Some(versions) = fetch_versions_for(client, package)? else {
+    let Some(versions) = fetch_versions_for(client, package, as_of, rust_version)? else {
         return Ok(None);
     };
     let versions = versions.filter(move |version| {
@@ -117,13 +192,20 @@ pub enum LatestVersion {
 }
 
 /// Fetch the latest versions of a crate that is considered a major update for _any_ of the given
-/// [`VersionReq`]s and has not been yanked
+/// [`VersionReq`]s and has not been yanked (see [`fetch_versions_for`] for `as_of` &
+/// `rust_version`)
+///
+/// Since the [`Ord`] of [`Version`] also orders build metadata, the choice is deterministic even
+/// if several versions only differ in it.
 pub fn fetch_latest_major_update_for(
     client: &SyncClient,
     package: &str,
     reqs: impl Iterator<Item: Borrow<VersionReq>> + Clone,
+    as_of: Option<NaiveDate>,
+    rust_version: Option<&Version>,
 ) -> Result<LatestVersion> {
-    let Some(versions) = fetch_major_updates_for(client, package, reqs)? else {
+    let Some(versions) = fetch_major_updates_for(client, package, reqs, as_of, rust_version)?
+    else {
         return Ok(LatestVersion::CrateNotFound);
     };
     let newest = versions.max();
@@ -148,12 +230,53 @@ impl DependencyMention {
     }
 }
 
+/// Why a dependency in a manifest isn't considered for major updates
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum SkipReason {
+    /// A `git` dependency
+    Git,
+    /// A `path` dependency
+    Path,
+    /// A `path` dependency with a `version`, which only applies to consumers of the published
+    /// crate since the workspace always resolves the local crate
+    PathWithVersion,
+    /// A dependency from a registry other than [crates.io]
+    Registry,
+    /// A dependency inherited from the workspace with `workspace = true`, which is considered via
+    /// the workspace manifest instead
+    Workspace,
+    /// A dependency without any `version`
+    MissingVersion,
+}
+
+/// A direct dependency that isn't considered for major updates, see [`SkipReason`]
+#[derive(Clone, Debug, Serialize)]
+pub struct SkippedDependency {
+    /// The name of the package (not the potentially renamed dependency)
+    pub name: String,
+    /// The manifest the dependency is listed in
+    pub manifest: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// A crate that is required with different version requirements in several dependency tables of
+/// the same manifest, see [`ManifestDependencySet::inconsistent_requirements`]
+#[derive(Clone, Debug, Serialize)]
+pub struct InconsistentRequirements {
+    pub name: String,
+    pub manifest: PathBuf,
+    /// The distinct version requirements, in the order of the tables
+    pub requirements: Vec<VersionReq>,
+}
+
 /// A set of manifests with the associated direct dependencies from [crates.io], with all instances
 /// of their version being requested
 pub struct ManifestDependencySet {
     pub manifests: ManifestSet,
     /// Maps crate names to [`DependencyMention`]s
     pub dependencies: BTreeMap<String, Vec<DependencyMention>>,
+    /// The direct dependencies that aren't considered for major updates
+    pub skipped: Vec<SkippedDependency>,
 }
 
 impl ManifestDependencySet {
@@ -166,9 +289,9 @@ impl ManifestDependencySet {
             .as_table()
             .get("target")
             .map(|target| {
-                target.as_table_like().ok_or_else(|| {
-                    eyre!("Invalid target table in {:?} at `target`", manifest.path())
-                })
+                target
+                    .as_table_like()
+                    .ok_or_else(|| invalid_manifest(manifest, "`target` isn't a table"))
             })
             .transpose()?
             .into_iter()
@@ -191,14 +314,17 @@ impl ManifestDependencySet {
     fn read_version(manifest: &MutableTomlFile, path: &[String]) -> Result<VersionReq> {
         let version = manifest
             .path_lookup(path)
-            .expect("Version path lookup failed (maybe the `MutableTomlFile` changed?)")
-            .as_str()
             .ok_or_else(|| {
-                eyre!(
-                    "Invalid `version`/immediate value in {path:?} at {:?}",
-                    manifest.path()
+                invalid_manifest(
+                    manifest,
+                    format!(
+                        "Version path lookup failed for {path:?} (maybe the `MutableTomlFile` \
+                        changed?)"
+                    ),
                 )
             })?
+            .as_str()
+            .ok_or_else(|| invalid_manifest(manifest, format!("Invalid `version` at {path:?}")))?
             .parse::<VersionReq>()?;
         Ok(version)
     }
@@ -208,6 +334,7 @@ impl ManifestDependencySet {
         manifest_idx: usize,
         manifest: &MutableTomlFile,
         direct_dependencies: &mut BTreeMap<String, Vec<DependencyMention>>,
+        skipped: &mut Vec<SkippedDependency>,
     ) -> Result<()> {
         for dep_path in Self::dependency_toml_paths(manifest)? {
             let Some(dependencies) = manifest.path_lookup(dep_path) else {
@@ -215,10 +342,7 @@ impl ManifestDependencySet {
             };
 
             let dependencies = dependencies.as_table_like().ok_or_else(|| {
-                eyre!(
-                    "Invalid dependency table in {:?} at {dep_path}",
-                    manifest.path()
-                )
+                invalid_manifest(manifest, format!("Invalid dependency table at {dep_path}"))
             })?;
 
             for (name, dependency) in dependencies.iter() {
@@ -227,18 +351,37 @@ impl ManifestDependencySet {
                         let package = match dependency.get("package") {
                             None => name,
                             Some(package) => package.as_str().ok_or_else(|| {
-                                eyre!(
-                                    "Invalid `package` value in {:?} at {dep_path}.{name:?}",
-                                    manifest.path()
+                                invalid_manifest(
+                                    manifest,
+                                    format!("Invalid `package` value at {dep_path}.{name:?}"),
                                 )
                             })?,
                         };
 
-                        if dependency.contains_key("registry")
-                            || !dependency.contains_key("version")
-                            || dependency.contains_key("git")
-                            || dependency.contains_key("path")
-                        {
+                        let skip_reason = if dependency.contains_key("git") {
+                            Some(SkipReason::Git)
+                        } else if dependency.contains_key("path") {
+                            if dependency.contains_key("version") {
+                                Some(SkipReason::PathWithVersion)
+                            } else {
+                                Some(SkipReason::Path)
+                            }
+                        } else if dependency.contains_key("registry") {
+                            Some(SkipReason::Registry)
+                        } else if dependency.contains_key("workspace") {
+                            Some(SkipReason::Workspace)
+                        } else if !dependency.contains_key("version") {
+                            Some(SkipReason::MissingVersion)
+                        } else {
+                            None
+                        };
+
+                        if let Some(reason) = skip_reason {
+                            skipped.push(SkippedDependency {
+                                name: package.to_owned(),
+                                manifest: manifest.path().to_owned(),
+                                reason,
+                            });
                             continue;
                         }
 
@@ -276,16 +419,41 @@ impl ManifestDependencySet {
         let manifests = ManifestSet::collect(metadata)?;
 
         let mut dependencies = BTreeMap::new();
+        let mut skipped = Vec::new();
         for (idx, manifest) in manifests.manifests.iter().enumerate() {
-            Self::collect_dependencies(idx, manifest, &mut dependencies)?;
+            Self::collect_dependencies(idx, manifest, &mut dependencies, &mut skipped)?;
         }
 
         Ok(ManifestDependencySet {
             manifests,
             dependencies,
+            skipped,
         })
     }
 
+    /// Returns the manifests that require the crate with the given name with different version
+    /// requirements in several dependency tables (e.g. `dependencies` & a `target` table), which
+    /// happens if only some of them were eligible for a major update
+    pub fn inconsistent_requirements(&self, name: &str) -> Vec<InconsistentRequirements> {
+        let mut by_manifest = BTreeMap::<usize, Vec<&VersionReq>>::new();
+        for mention in self.dependencies.get(name).into_iter().flatten() {
+            let requirements = by_manifest.entry(mention.manifest_idx).or_default();
+            if !requirements.contains(&&mention.version) {
+                requirements.push(&mention.version);
+            }
+        }
+
+        by_manifest
+            .into_iter()
+            .filter(|(_, requirements)| requirements.len() > 1)
+            .map(|(manifest_idx, requirements)| InconsistentRequirements {
+                name: name.to_owned(),
+                manifest: self.manifests.manifests[manifest_idx].path().to_owned(),
+                requirements: requirements.into_iter().cloned().collect(),
+            })
+            .collect()
+    }
+
     /// Commit all changes made to the [`ManifestSet`] (see [`MutableTomlFile::commit`])
     pub fn commit(&mut self) -> Result<()> {
         self.manifests.write_back()?;
@@ -326,13 +494,14 @@ impl ManifestDependencySet {
         if errors.is_empty() {
             Ok(())
         } else {
-            Err(eyre!("Failed to roll back:\n{errors:?}"))
+            Err(ResolveDiffError::RollbackFailed(errors).into())
         }
     }
 }
 
 /// A set of manifests for a workspace
 pub struct ManifestSet {
+    /// The workspace manifest comes first, followed by the member manifests (if it's a workspace)
     manifests: Vec<MutableTomlFile>,
     lock_path: PathBuf,
     last_lock_contents: String,
@@ -341,8 +510,8 @@ pub struct ManifestSet {
 impl ManifestSet {
     /// Collect all manifests from an [`IndexedMetadata`]
     pub fn collect(metadata: &IndexedMetadata) -> Result<Self> {
-        let workspace_manifest = metadata.workspace_root.join("Cargo.toml");
-        let lock_path = workspace_manifest.with_extension("lock").into();
+        let workspace_manifest = &metadata.root_manifest;
+        let lock_path = metadata.workspace_root.join("Cargo.lock").into();
 
         let mut member_manifests = metadata
             .packages
@@ -351,13 +520,13 @@ impl ManifestSet {
             .map(|(_, pkg)| &pkg.manifest_path)
             .collect::<Vec<_>>();
 
-        let isnt_workspace = matches!(*member_manifests, [single] if *single == workspace_manifest);
+        let isnt_workspace = matches!(*member_manifests, [single] if single == workspace_manifest);
 
         if isnt_workspace {
             member_manifests.clear();
         }
 
-        let manifests = iter::once(&workspace_manifest)
+        let manifests = iter::once(workspace_manifest)
             .chain(member_manifests)
             .map(MutableTomlFile::open)
             .collect::<Result<Vec<_>>>()?;
@@ -375,6 +544,115 @@ impl ManifestSet {
         &self.manifests
     }
 
+    /// Bail if any of the manifests (e.g. of a workspace member included via a relative path)
+    /// isn't within the given workspace root, since editing those would be surprising
+    pub fn ensure_within(&self, workspace_root: &Path) -> Result<()> {
+        let external = self
+            .manifests
+            .iter()
+            .map(MutableTomlFile::path)
+            .filter(|path| !path.starts_with(workspace_root))
+            .collect::<Vec<_>>();
+
+        if !external.is_empty() {
+            return Err(ResolveDiffError::ExternalManifests {
+                workspace_root: workspace_root.to_owned(),
+                manifests: external.into_iter().map(Path::to_owned).collect(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the effective `rust-version` of a package manifest that is part of this set,
+    /// following `rust-version.workspace = true` to `workspace.package.rust-version` in the
+    /// workspace manifest
+    ///
+    /// Returns `None` if no `rust-version` is set. Missing minor & patch versions are treated as
+    /// `0` (so `1.70` is `1.70.0`).
+    pub fn effective_rust_version(&self, manifest: &MutableTomlFile) -> Result<Option<Version>> {
+        let Some(rust_version) = manifest.path_lookup(["package", "rust-version"]) else {
+            return Ok(None);
+        };
+
+        let (manifest, rust_version) = if let Some(table) = rust_version.as_table_like() {
+            if table.get("workspace").and_then(toml_edit::Item::as_bool) != Some(true) {
+                return Err(invalid_manifest(manifest, "Invalid `package.rust-version`"));
+            }
+
+            // NOTE: `collect` puts the workspace manifest first
+            let workspace_manifest = &self.manifests[0];
+            let Some(rust_version) =
+                workspace_manifest.path_lookup(["workspace", "package", "rust-version"])
+            else {
+                return Err(invalid_manifest(
+                    workspace_manifest,
+                    format!(
+                        "{:?} inherits `rust-version` from the workspace, but it isn't set",
+                        manifest.path()
+                    ),
+                ));
+            };
+            (workspace_manifest, rust_version)
+        } else {
+            (manifest, rust_version)
+        };
+
+        let rust_version = rust_version.as_str().and_then(parse_rust_version);
+        let invalid = || invalid_manifest(manifest, "Invalid `rust-version` value");
+        rust_version.ok_or_else(invalid).map(Some)
+    }
+
+    /// Returns the lowest effective `rust-version` (see [`Self::effective_rust_version`]) of the
+    /// manifests containing any of the given mentions
+    ///
+    /// Mentions in the workspace manifest without a `[package]` use
+    /// `workspace.package.rust-version` instead. Returns `None` if none of them set one.
+    pub fn rust_version_for(&self, mentions: &[DependencyMention]) -> Result<Option<Version>> {
+        let mut lowest = None::<Version>;
+        for mention in mentions {
+            let manifest = self.manifest_for(mention);
+            let rust_version =
+                if mention.manifest_idx == 0 && manifest.path_lookup(["package"]).is_none() {
+                    let path = ["workspace", "package", "rust-version"];
+                    let invalid = || invalid_manifest(manifest, "Invalid `rust-version` value");
+                    manifest
+                        .path_lookup(path)
+                        .map(|item| {
+                            item.as_str()
+                                .and_then(parse_rust_version)
+                                .ok_or_else(invalid)
+                        })
+                        .transpose()?
+                } else {
+                    self.effective_rust_version(manifest)?
+                };
+            lowest = lowest.into_iter().chain(rust_version).min();
+        }
+        Ok(lowest)
+    }
+
+    /// Copy all manifests (as they currently are on disk) into `dir`, keeping their paths
+    /// relative to the workspace root
+    pub fn back_up_to(&self, dir: &Path) -> Result<()> {
+        let workspace_root = self.lock_path.parent().expect("ends in /Cargo.lock");
+
+        for manifest in &self.manifests {
+            let relative = manifest.path().strip_prefix(workspace_root).map_err(|_| {
+                ResolveDiffError::ExternalManifests {
+                    workspace_root: workspace_root.to_owned(),
+                    manifests: vec![manifest.path().to_owned()],
+                }
+            })?;
+            let backup = dir.join(relative);
+            fs::create_dir_all(backup.parent().expect("ends in /Cargo.toml"))?;
+            fs::copy(manifest.path(), backup)?;
+        }
+
+        Ok(())
+    }
+
     pub fn as_slice_mut(&mut self) -> &mut [MutableTomlFile] {
         &mut self.manifests
     }
@@ -422,18 +700,36 @@ impl ManifestSet {
         &mut self,
         mention: &mut DependencyMention,
         version: VersionReq,
-    ) {
-        let Some(toml_edit::Value::String(toml_version)) = self
-            .manifest_mut_for(mention)
+    ) -> Result<()> {
+        let manifest = self.manifest_mut_for(mention);
+        let manifest_path = manifest.path().to_owned();
+        let Some(toml_edit::Value::String(toml_version)) = manifest
             .path_lookup_mut(&mention.toml_path)
             .and_then(toml_edit::Item::as_value_mut)
         else {
-            panic!("Version path lookup failed (maybe the `MutableTomlFile` changed?)");
+            return Err(ResolveDiffError::InvalidManifest {
+                path: manifest_path,
+                message: format!(
+                    "Version path lookup failed for {:?}, or it isn't a string (maybe the \
+                    `MutableTomlFile` changed?)",
+                    mention.toml_path
+                ),
+            }
+            .into());
         };
+        // NOTE: Keep equivalent requirements as written (e.g. `">=1.2,<2"`, which would otherwise
+        // be normalized to `">=1.2, <2"`), to avoid spurious changes
+        if toml_version.value().parse::<VersionReq>().ok() == Some(version.clone()) {
+            mention.version = version;
+            return Ok(());
+        }
+
         let decor = toml_version.decor().clone();
+        // NOTE: Keep the style of the original requirement, to avoid spurious changes
+        let explicit_caret = toml_version.value().trim_start().starts_with('^');
 
         let as_string = match *version.comparators {
-            [ref single] if single.op == semver::Op::Caret => {
+            [ref single] if single.op == semver::Op::Caret && !explicit_caret => {
                 let mut out = version.to_string();
                 if out.starts_with('^') {
                     out.remove(0); // Remove the caret
@@ -447,6 +743,7 @@ impl ManifestSet {
         *toml_version.decor_mut() = decor;
 
         mention.version = version;
+        Ok(())
     }
 
     /// Change a dependency version in memory only (requires calling a `write_back` or `commit`
@@ -455,10 +752,11 @@ impl ManifestSet {
         &mut self,
         mentions: &mut [DependencyMention],
         version: &VersionReq,
-    ) {
+    ) -> Result<()> {
         for mention in mentions {
-            self.write_version_to_memory(mention, version.clone());
+            self.write_version_to_memory(mention, version.clone())?;
         }
+        Ok(())
     }
 
     /// Change a dependency version
@@ -467,7 +765,7 @@ impl ManifestSet {
         mention: &mut DependencyMention,
         version: VersionReq,
     ) -> Result<()> {
-        self.write_version_to_memory(mention, version);
+        self.write_version_to_memory(mention, version)?;
         self.write_back_for(mention)?;
         Ok(())
     }
@@ -478,13 +776,21 @@ impl ManifestSet {
         mentions: &mut [DependencyMention],
         version: &VersionReq,
     ) -> Result<()> {
-        self.write_versions_to_memory(mentions, version);
+        self.write_versions_to_memory(mentions, version)?;
         self.write_back_for_all(mentions)?;
         Ok(())
     }
 
     /// Change a dependency version in memory if it is considered a major update
-    pub fn update_version_in_memory(&mut self, mention: &mut DependencyMention, version: &Version) {
+    ///
+    /// The written caret requirement keeps the pre-release of `version` (e.g. `2.0.0-rc.1`), so it
+    /// still matches `version` when read back. Build metadata is dropped, since version
+    /// requirements can't express it (and `cargo` ignores it for matching anyway).
+    pub fn update_version_in_memory(
+        &mut self,
+        mention: &mut DependencyMention,
+        version: &Version,
+    ) -> Result<()> {
         if is_major_update_for(&mention.version, version) {
             self.write_version_to_memory(
                 mention,
@@ -497,8 +803,9 @@ impl ManifestSet {
                         pre: version.pre.clone(),
                     }],
                 },
-            );
+            )?;
         }
+        Ok(())
     }
 
     /// Change dependency versions in memory for each mention for which it is considered a major
@@ -507,10 +814,11 @@ impl ManifestSet {
         &mut self,
         mentions: &mut [DependencyMention],
         version: &Version,
-    ) {
+    ) -> Result<()> {
         for mention in mentions {
-            self.update_version_in_memory(mention, version);
+            self.update_version_in_memory(mention, version)?;
         }
+        Ok(())
     }
 
     /// Change a dependency version if it is considered a major update
@@ -519,7 +827,7 @@ impl ManifestSet {
         mention: &mut DependencyMention,
         version: &Version,
     ) -> Result<()> {
-        self.update_version_in_memory(mention, version);
+        self.update_version_in_memory(mention, version)?;
         self.write_back_for(mention)?;
         Ok(())
     }
@@ -530,7 +838,7 @@ impl ManifestSet {
         mentions: &mut [DependencyMention],
         version: &Version,
     ) -> Result<()> {
-        self.update_versions_in_memory(mentions, version);
+        self.update_versions_in_memory(mentions, version)?;
         self.write_back_for_all(mentions)?;
         Ok(())
     }
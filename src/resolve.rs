@@ -3,26 +3,52 @@
 //! Walks the `resolve` graph in an [`IndexedMetadata`] to gather dependency kinds & inclusion
 //! reasons
 
-use crate::Platform;
 use crate::indexed::IndexedMetadata;
+use crate::major_updates::{
+    DependencyMention, LatestVersion, ManifestDependencySet, fetch_latest_major_update_for,
+};
+use crate::util::{CargoOptions, TempWorkspace, update};
+use crate::{Platform, ResolveDiffError};
 use camino::{Utf8Path, Utf8PathBuf};
-use cargo_metadata::PackageId;
-use color_eyre::Result;
+use cargo_metadata::{Edition, PackageId};
+use chrono::NaiveDate;
+use color_eyre::{Report, Result};
+use crates_io_api::SyncClient;
 use semver::Version;
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
     borrow::Borrow,
     collections::{BTreeMap, BTreeSet, btree_map},
-    fmt,
+    fmt, iter,
     path::Path,
+    str::FromStr,
 };
 
-fn shorten_path_relative_to<'a>(relative: &Utf8Path, path: &'a Utf8Path) -> &'a Utf8Path {
+/// Make `path` relative to `relative`, going up with `..` for paths outside of it.
+///
+/// This keeps local identifiers identical between workspaces at different locations with the
+/// same layout. Paths without any common prefix (e.g. on different drives) are kept as is.
+fn shorten_path_relative_to(relative: &Utf8Path, path: &Utf8Path) -> Utf8PathBuf {
     if path.starts_with(relative) {
-        path.strip_prefix(relative).expect("checked above")
-    } else {
-        path
+        return path
+            .strip_prefix(relative)
+            .expect("checked above")
+            .to_owned();
+    }
+
+    let common = relative
+        .components()
+        .zip(path.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return path.to_owned();
     }
+
+    let up = relative.components().count() - common;
+    let mut out = iter::repeat_n("..", up).collect::<Utf8PathBuf>();
+    out.extend(path.components().skip(common));
+    out
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
@@ -37,7 +63,7 @@ impl AnyCrateIdent {
             AnyCrateIdent::CratesIo(package.name.to_string())
         } else {
             let path = package.manifest_path.parent().expect("ends in /Cargo.toml");
-            AnyCrateIdent::Local(shorten_path_relative_to(relative, path).to_owned())
+            AnyCrateIdent::Local(shorten_path_relative_to(relative, path))
         }
     }
 
@@ -71,11 +97,60 @@ impl fmt::Display for SpecificCrateIdent {
     }
 }
 
+/// Parses the `"<name> <version>"` form of the [`fmt::Display`] implementation (the quotes are
+/// optional)
+impl FromStr for SpecificCrateIdent {
+    type Err = ResolveDiffError;
+
+    fn from_str(ident: &str) -> Result<Self, Self::Err> {
+        let error = |message: String| ResolveDiffError::InvalidCrateIdent {
+            ident: ident.to_owned(),
+            message,
+        };
+
+        let unquoted = (ident.strip_prefix('"'))
+            .and_then(|ident| ident.strip_suffix('"'))
+            .unwrap_or(ident);
+        let Some((name, version)) = unquoted.split_once(' ') else {
+            return Err(error("expected `<name> <version>`".to_owned()));
+        };
+        if name.is_empty() {
+            return Err(error("empty crate name".to_owned()));
+        }
+
+        let version = version
+            .parse()
+            .map_err(|err: semver::Error| error(err.to_string()))?;
+        Ok(SpecificCrateIdent {
+            name: name.to_owned(),
+            version,
+        })
+    }
+}
+
+/// Deserializes from both the `{name, version}` object that [`Serialize`] produces and the
+/// `"<name> <version>"` form (see [`FromStr`])
+impl<'de> Deserialize<'de> for SpecificCrateIdent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Object { name: String, version: Version },
+            String(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Object { name, version } => Ok(SpecificCrateIdent { name, version }),
+            Repr::String(ident) => ident.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
 /// A [crates.io] dependency or a local dependency
 ///
 /// (At the moment `git` dependencies get resolved as [crates.io] dependencies even if they are
 /// not)
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize)]
 pub enum SpecificAnyCrateIdent {
     Local(Utf8PathBuf),
     CratesIo(SpecificCrateIdent),
@@ -129,6 +204,21 @@ impl DependencyKind {
         only_debug_builds: false,
     };
 
+    /// Whether the crate gets executed at some point at build time
+    pub const fn is_build_active(self) -> bool {
+        self.run_at_build
+    }
+
+    /// Whether the crate is included in release builds (outside of `dev-dependencies`)
+    pub const fn is_release_included(self) -> bool {
+        !self.only_debug_builds
+    }
+
+    /// Whether the crate is only ever built as a `dev-dependency`
+    pub const fn is_test_only(self) -> bool {
+        self.only_debug_builds
+    }
+
     /// Combine dependency kinds between a parent dependency and its edge to a child.
     ///
     /// If either is a build dependency, this sets `run_at_build`, and if either is only included
@@ -176,7 +266,12 @@ pub struct IncludedDependencyReason {
     /// (if this is `None`, the dependency in the `Cargo.toml` is `parent`)
     pub intermediate_root_dependency: Option<SpecificAnyCrateIdent>,
     /// The dependency that directly depended on this crate
+    ///
+    /// If `deep` is set, this is the same as `intermediate_root_dependency` instead.
     pub parent: SpecificAnyCrateIdent,
+    /// This crate is included deeper than [`ResolveOptions::max_reason_depth`], so the actual
+    /// parent isn't tracked
+    pub deep: bool,
 }
 
 impl fmt::Debug for IncludedDependencyReason {
@@ -192,7 +287,9 @@ impl fmt::Display for IncludedDependencyReason {
         }
         if let Some(ref intermediate) = self.intermediate_root_dependency {
             write!(f, ".{intermediate}")?;
-            if self.parent != *intermediate {
+            if self.deep {
+                write!(f, "...(deep transitive)")?;
+            } else if self.parent != *intermediate {
                 write!(f, "...{}", self.parent)?;
             }
         }
@@ -206,6 +303,87 @@ impl Serialize for IncludedDependencyReason {
     }
 }
 
+/// An [`IncludedDependencyReason`] with its platforms, serialized as nested objects instead of
+/// its [`Display`](fmt::Display) string, see [`structured_reasons`]
+#[derive(Serialize, Debug)]
+pub struct StructuredReason<'a> {
+    pub kind: DependencyKind,
+    pub root: &'a Utf8Path,
+    pub intermediate: Option<&'a SpecificAnyCrateIdent>,
+    pub parent: &'a SpecificAnyCrateIdent,
+    pub deep: bool,
+    pub platforms: &'a BTreeSet<Platform>,
+}
+
+/// Returns the [`StructuredReason`]s for a set of [`Reasons`], for tools that don't want to parse
+/// the string form
+pub fn structured_reasons(reasons: &Reasons) -> Vec<StructuredReason<'_>> {
+    reasons
+        .iter()
+        .map(|(reason, platforms)| StructuredReason {
+            kind: reason.kind,
+            root: &reason.root,
+            intermediate: reason.intermediate_root_dependency.as_ref(),
+            parent: &reason.parent,
+            deep: reason.deep,
+            platforms,
+        })
+        .collect()
+}
+
+/// Replaces the local paths in [`IncludedDependencyReason`]s (the directories of their `root`s and
+/// of [`SpecificAnyCrateIdent::Local`] crates) with anonymized tokens (`crate-1`, `crate-2`, …),
+/// see [`Resolved::redact_local_paths`]
+///
+/// Every directory gets the same token across all [`Resolved`]s redacted with the same instance,
+/// so diffs between them stay consistent. The workspace root itself (an empty path) is kept.
+#[derive(Default, Debug)]
+pub struct LocalPathRedactor {
+    tokens: BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+}
+
+impl LocalPathRedactor {
+    /// Returns the token for a local crate directory (relative to the workspace root)
+    pub fn redact_dir(&mut self, dir: &Utf8Path) -> Utf8PathBuf {
+        if dir.as_str().is_empty() {
+            return dir.to_owned();
+        }
+
+        let next = self.tokens.len() + 1;
+        (self.tokens.entry(dir.to_owned()))
+            .or_insert_with(|| format!("crate-{next}").into())
+            .clone()
+    }
+
+    /// Returns a manifest path with its directory replaced by its token
+    pub fn redact_manifest(&mut self, manifest: &Utf8Path) -> Utf8PathBuf {
+        let dir = self.redact_dir(manifest.parent().unwrap_or(Utf8Path::new("")));
+        match manifest.file_name() {
+            Some(file_name) => dir.join(file_name),
+            None => dir,
+        }
+    }
+
+    fn redact_ident(&mut self, ident: SpecificAnyCrateIdent) -> SpecificAnyCrateIdent {
+        match ident {
+            SpecificAnyCrateIdent::Local(dir) => {
+                SpecificAnyCrateIdent::Local(self.redact_dir(&dir))
+            }
+            SpecificAnyCrateIdent::CratesIo(_) => ident,
+        }
+    }
+
+    fn redact_reason(&mut self, reason: IncludedDependencyReason) -> IncludedDependencyReason {
+        IncludedDependencyReason {
+            root: self.redact_manifest(&reason.root),
+            intermediate_root_dependency: (reason.intermediate_root_dependency)
+                .map(|intermediate| self.redact_ident(intermediate)),
+            parent: self.redact_ident(reason.parent),
+            ..reason
+        }
+    }
+}
+
 /// The reasons for a dependencies inclusion mapped to a set of platforms.
 ///
 /// NOTE: This set may be empty if an [`IndexedMetadata`] was included that didn't filter for a
@@ -214,24 +392,136 @@ pub type Reasons = BTreeMap<IncludedDependencyReason, BTreeSet<Platform>>;
 
 /// NOTE: Only keeps track of platforms that are explicitly listed in [`IndexedMetadata`]s that
 /// were passed, or alternatively the platforms given to [`Resolved::resolve_for`].
+///
+/// The fields are only modified during resolution (e.g. to merge the `kind`s of all reasons), and
+/// can be read via the accessors.
+#[derive(Serialize, PartialEq, Eq)]
 pub struct IncludedDependencyVersion {
-    pub kind: DependencyKind,
-    pub has_build_rs: bool,
-    pub is_proc_macro: bool,
+    pub(crate) kind: DependencyKind,
+    pub(crate) has_build_rs: bool,
+    pub(crate) is_proc_macro: bool,
+    pub(crate) reasons: Reasons,
+    pub(crate) platforms: BTreeSet<Platform>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) links: Option<String>,
+}
+
+impl IncludedDependencyVersion {
+    /// The merged kind of all reasons for the inclusion of this crate
+    pub fn kind(&self) -> DependencyKind {
+        self.kind
+    }
+
+    /// Whether this crate has a build script
+    pub fn has_build_rs(&self) -> bool {
+        self.has_build_rs
+    }
+
+    /// Whether this crate is a proc-macro
+    pub fn is_proc_macro(&self) -> bool {
+        self.is_proc_macro
+    }
+
     /// The reasons for the inclusion of this crate
-    pub reasons: Reasons,
+    pub fn reasons(&self) -> &Reasons {
+        &self.reasons
+    }
+
     /// The platforms this crate is included for that were filtered for in an [`IndexedMetadata`]
-    pub platforms: BTreeSet<Platform>,
+    pub fn platforms(&self) -> &BTreeSet<Platform> {
+        &self.platforms
+    }
+
+    /// The `links` key of the manifest of this crate (the native library it links to), if any
+    pub fn links(&self) -> Option<&str> {
+        self.links.as_deref()
+    }
 }
 
 /// The set of included packages, mapping from the crate name to a map from versions to the actual
 /// metadata
 pub type Included = BTreeMap<String, BTreeMap<Version, IncludedDependencyVersion>>;
 
+/// The set of dependency edge kinds to follow while resolving
+///
+/// NOTE: Proc-macro crates are usually reached via normal dependency edges, so they are still
+/// included (& marked as run at build time) if build dependencies aren't followed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DependencyKindFilter {
+    /// Follow `dependencies`
+    pub normal: bool,
+    /// Follow `build-dependencies`
+    pub build: bool,
+    /// Follow `dev-dependencies` of workspace members
+    pub dev: bool,
+}
+
+impl DependencyKindFilter {
+    /// Follow dependency edges of all kinds
+    pub const ALL: Self = DependencyKindFilter {
+        normal: true,
+        build: true,
+        dev: true,
+    };
+
+    /// Returns whether dependency edges of the given kind get followed
+    pub fn allows(&self, kind: cargo_metadata::DependencyKind) -> bool {
+        match kind {
+            cargo_metadata::DependencyKind::Normal => self.normal,
+            cargo_metadata::DependencyKind::Build => self.build,
+            cargo_metadata::DependencyKind::Development => self.dev,
+            _ => true,
+        }
+    }
+}
+
+impl Default for DependencyKindFilter {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Options for walking the `resolve` graph of an [`IndexedMetadata`]
+#[derive(Clone, Debug, Default)]
+pub struct ResolveOptions {
+    /// The kinds of dependency edges to follow
+    pub kinds: DependencyKindFilter,
+    /// The names or package IDs of the workspace members to start resolution from, or all
+    /// default members if this is empty
+    pub members: Vec<String>,
+    /// The depth (with direct dependencies of workspace members at depth `1`) beyond which the
+    /// parents of crates aren't tracked in their reasons, to bound the number of reasons
+    ///
+    /// This doesn't change which crates are included.
+    pub max_reason_depth: Option<usize>,
+    /// The platform that build scripts, proc-macros & their dependencies are built for (when
+    /// resolving for a specific platform), such as with [`host_platform`]
+    ///
+    /// If this is set, crates that are run at build time are attributed to it instead of the
+    /// platform the dependencies were resolved for, since `cargo` resolves them for the host
+    /// when cross-compiling.
+    ///
+    /// [`host_platform`]: crate::util::host_platform
+    pub host_platform: Option<Platform>,
+    /// Resolve the graph only once for several platforms if `cargo metadata` produced exactly the
+    /// same graph for them, see [`IndexedMetadata::merge_identical`]
+    ///
+    /// This doesn't change the result, but comparing the graphs isn't free either, and every
+    /// distinct graph is kept in memory until all platforms are gathered (instead of only up to
+    /// [`CargoOptions::threads`] at once).
+    pub merge_identical_platforms: bool,
+}
+
 /// The set of fully resolved information ready for diffing with [`crate::diff::Diff`]
+///
+/// This serializes everything except for `full_metadata`.
+#[derive(Serialize)]
 pub struct Resolved {
     /// The [`IndexedMetadata`] this is based on
+    #[serde(skip)]
     pub full_metadata: IndexedMetadata,
+    /// The platforms dependencies were specifically resolved for
+    pub platforms: BTreeSet<Platform>,
     /// The set of packages that are included in the filtered platforms, or all packages if an
     /// unfiltered [`IndexedMetadata`] was included
     pub included: Included,
@@ -240,24 +530,178 @@ pub struct Resolved {
 }
 
 impl Resolved {
+    /// Returns the included versions of the crate with the given name, if any
+    pub fn crate_versions(
+        &self,
+        name: &str,
+    ) -> Option<&BTreeMap<Version, IncludedDependencyVersion>> {
+        self.included.get(name)
+    }
+
+    /// Returns whether the given version of the crate with the given name is included
+    pub fn is_included(&self, name: &str, version: &Version) -> bool {
+        self.crate_versions(name)
+            .is_some_and(|versions| versions.contains_key(version))
+    }
+
+    /// Returns the names of all included crates in order
+    pub fn all_crate_names(&self) -> impl Iterator<Item = &str> {
+        self.included.keys().map(String::as_str)
+    }
+
+    /// Returns the name of the workspace member with the given `root` manifest (as in
+    /// [`IncludedDependencyReason::root`]), if any
+    pub fn member_for_root(&self, root: &Utf8Path) -> Option<&str> {
+        let metadata = &self.full_metadata;
+        metadata
+            .workspace_members
+            .iter()
+            .map(|pkg| &metadata.packages[pkg])
+            .find(|package| {
+                shorten_path_relative_to(&metadata.workspace_root, &package.manifest_path) == root
+            })
+            .map(|package| package.name.as_str())
+    }
+
+    /// Returns the crates with more than one included version, with those versions
+    pub fn duplicates(&self) -> BTreeMap<String, Vec<Version>> {
+        self.included
+            .iter()
+            .filter(|(_, versions)| versions.len() > 1)
+            .map(|(name, versions)| (name.clone(), versions.keys().cloned().collect()))
+            .collect()
+    }
+
+    /// Returns the crates that are only included because proc-macro crates (directly or via other
+    /// such crates) depend on them, which means they only run at build time
+    ///
+    /// The proc-macro crates themselves aren't part of this, unless they're only included for
+    /// another proc-macro crate as well.
+    pub fn proc_macro_closure(&self) -> BTreeSet<SpecificCrateIdent> {
+        let versions = || {
+            self.included.iter().flat_map(|(name, versions)| {
+                versions.iter().map(move |(version, info)| {
+                    let ident = SpecificCrateIdent {
+                        name: name.clone(),
+                        version: version.clone(),
+                    };
+                    (ident, info)
+                })
+            })
+        };
+
+        let mut closure = BTreeSet::new();
+        loop {
+            let is_proc_macro_or_closure = |parent: &SpecificAnyCrateIdent| match parent {
+                SpecificAnyCrateIdent::Local(_) => false,
+                SpecificAnyCrateIdent::CratesIo(parent) => {
+                    closure.contains(parent)
+                        || (self.crate_versions(&parent.name))
+                            .and_then(|versions| versions.get(&parent.version))
+                            .is_some_and(|info| info.is_proc_macro)
+                }
+            };
+
+            let new = versions()
+                .filter(|(ident, info)| {
+                    !closure.contains(ident)
+                        && !info.reasons.is_empty()
+                        && (info.reasons.keys())
+                            .all(|reason| is_proc_macro_or_closure(&reason.parent))
+                })
+                .map(|(ident, _)| ident)
+                .collect::<Vec<_>>();
+
+            if new.is_empty() {
+                return closure;
+            }
+            closure.extend(new);
+        }
+    }
+
+    /// Returns the crates that are only included on a single one of the resolved `platforms`, for
+    /// each of them
+    ///
+    /// Crates included on several platforms (or without specific platforms) aren't part of this.
+    pub fn platform_breakdown(&self) -> BTreeMap<Platform, BTreeSet<SpecificCrateIdent>> {
+        let mut out = (self.platforms.iter())
+            .map(|platform| (platform.clone(), BTreeSet::new()))
+            .collect::<BTreeMap<_, _>>();
+
+        for (name, versions) in &self.included {
+            for (version, info) in versions {
+                let mut platforms = info.platforms.iter();
+                let (Some(platform), None) = (platforms.next(), platforms.next()) else {
+                    continue;
+                };
+                out.entry(platform.clone())
+                    .or_default()
+                    .insert(SpecificCrateIdent {
+                        name: name.clone(),
+                        version: version.clone(),
+                    });
+            }
+        }
+
+        out
+    }
+
+    /// Replace the local paths in the reasons of all included crates with the tokens of
+    /// `redactor`, e.g. for output that gets shared outside of the team
+    ///
+    /// Since the reasons no longer match the manifests of the workspace, this should only be used
+    /// for output (e.g. [`Resolved::member_for_root`] won't find any redacted members).
+    pub fn redact_local_paths(&mut self, redactor: &mut LocalPathRedactor) {
+        for info in self.included.values_mut().flat_map(BTreeMap::values_mut) {
+            info.reasons = std::mem::take(&mut info.reasons)
+                .into_iter()
+                .map(|(reason, platforms)| (redactor.redact_reason(reason), platforms))
+                .collect();
+        }
+    }
+
+    /// Returns the `edition` of every workspace member by package name, see
+    /// [`IndexedMetadata::member_editions`]
+    pub fn member_editions(&self) -> BTreeMap<String, Edition> {
+        self.full_metadata.member_editions()
+    }
+
     /// Resolve everything only for a given platform given its filtered [`IndexedMetadata`] (or the
     /// unfiltered metadata if all platforms should be included)
-    fn resolve_platform(metadata: &IndexedMetadata, included: &mut Included) {
+    fn resolve_platform(
+        metadata: &IndexedMetadata,
+        included: &mut Included,
+        options: &ResolveOptions,
+    ) -> Result<()> {
+        let platforms = metadata.platform.iter().cloned().collect::<Vec<_>>();
+        Self::resolve_platforms(metadata, &platforms, included, options)
+    }
+
+    /// [`Resolved::resolve_platform`] for several platforms whose filtered [`IndexedMetadata`]
+    /// are identical at once (or for no specific platform if `platforms` is empty), see
+    /// [`IndexedMetadata::merge_identical`]
+    fn resolve_platforms(
+        metadata: &IndexedMetadata,
+        platforms: &[Platform],
+        included: &mut Included,
+        options: &ResolveOptions,
+    ) -> Result<()> {
         #[derive(Clone)]
-        enum TodoFrom<'a> {
-            Workspace(&'a Utf8Path),
+        enum TodoFrom {
+            Workspace(Utf8PathBuf),
             Dependency(IncludedDependencyReason),
         }
 
         struct Todo<'a> {
             kind: DependencyKind,
-            incoming_edge: TodoFrom<'a>,
+            incoming_edge: TodoFrom,
             pkg: &'a PackageId,
+            depth: usize,
         }
 
         let mut todos = metadata
-            .get_workspace_default_members()
-            .iter()
+            .select_workspace_members(&options.members)?
+            .into_iter()
             .map(|pkg| {
                 let path = shorten_path_relative_to(
                     &metadata.workspace_root,
@@ -267,13 +711,24 @@ impl Resolved {
                     kind: DependencyKind::NORMAL,
                     incoming_edge: TodoFrom::Workspace(path),
                     pkg,
+                    depth: 0,
                 }
             })
             .collect::<Vec<_>>();
 
+        // NOTE: Everything that determines the todos pushed for the dependencies of a package, see
+        // below
+        let mut explored = BTreeSet::new();
+
         while let Some(todo) = todos.pop() {
             let package = &metadata.packages[todo.pkg];
-            let node = &metadata.resolve[todo.pkg];
+            let Some(node) = metadata.resolve.get(todo.pkg) else {
+                return Err(ResolveDiffError::IncompleteMetadata(format!(
+                    "the package {} is missing from the resolve graph",
+                    todo.pkg
+                ))
+                .into());
+            };
 
             let package_ident = AnyCrateIdent::from_package(&metadata.workspace_root, package);
 
@@ -303,6 +758,13 @@ impl Resolved {
                 package_kind.run_at_build = true;
             }
 
+            let package_platforms = match options.host_platform {
+                Some(ref host) if package_kind.run_at_build && !platforms.is_empty() => {
+                    std::slice::from_ref(host)
+                }
+                _ => platforms,
+            };
+
             if let AnyCrateIdent::CratesIo(ref name) = package_ident {
                 let version = included
                     .entry(name.clone())
@@ -315,6 +777,7 @@ impl Resolved {
                     is_proc_macro,
                     reasons: BTreeMap::new(),
                     platforms: BTreeSet::new(),
+                    links: package.links.clone(),
                 });
 
                 let package_kind = version.kind.merged_with(package_kind);
@@ -327,22 +790,45 @@ impl Resolved {
                     TodoFrom::Workspace(_) => (),
                     TodoFrom::Dependency(ref reason) => {
                         let entry = version.reasons.entry(reason.clone()).or_default(); // This gets added even if we don't add a platform
-                        if let Some(platform) = metadata.platform.clone() {
-                            entry.insert(platform);
-                        }
+                        entry.extend(package_platforms.iter().cloned());
                     }
                 };
 
-                let new_platform = metadata
-                    .platform
-                    .clone()
-                    .is_some_and(|platform| version.platforms.insert(platform));
+                let mut new_platform = false;
+                for platform in package_platforms {
+                    new_platform |= version.platforms.insert(platform.clone());
+                }
 
                 if !(inserted_new || new_kind || new_platform) {
                     continue;
                 }
             }
 
+            // NOTE: Local packages get explored again for every incoming edge (as their reasons lead
+            // to different reasons for their dependencies). That can't loop forever with the
+            // `dev-dependency` cycles `cargo` allows, since `dev-dependencies` are only followed from
+            // workspace members, but this guarantees termination regardless by skipping explorations
+            // that would push exactly the same todos as an earlier one (the depth only matters up to
+            // where the reasons get `deep`):
+            let (root, intermediate_root_dependency) = match todo.incoming_edge {
+                TodoFrom::Workspace(ref root) => (root.clone(), None),
+                TodoFrom::Dependency(ref reason) => (
+                    reason.root.clone(),
+                    Some(reason.intermediate_root_dependency.clone()),
+                ),
+            };
+            let reason_depth =
+                (options.max_reason_depth).map_or(0, |max| todo.depth.min(max.max(2)));
+            if !explored.insert((
+                todo.pkg,
+                package_kind,
+                root,
+                intermediate_root_dependency,
+                reason_depth,
+            )) {
+                continue;
+            }
+
             let dep_parent = package_ident.with_version(&package.version);
 
             todos.extend(node.deps.iter().filter_map(|dep| {
@@ -351,14 +837,15 @@ impl Resolved {
                     .iter()
                     .filter(|kind| {
                         // Dev dependencies of dependencies are not relevant
-                        matches!(todo.incoming_edge, TodoFrom::Workspace(_))
-                            || kind.kind != cargo_metadata::DependencyKind::Development
+                        options.kinds.allows(kind.kind)
+                            && (matches!(todo.incoming_edge, TodoFrom::Workspace(_))
+                                || kind.kind != cargo_metadata::DependencyKind::Development)
                     })
                     .map(|kind| package_kind.then(kind.kind.into()))
                     .reduce(DependencyKind::merged_with)?;
 
                 let (root, intermediate_root_dependency) = match todo.incoming_edge {
-                    TodoFrom::Workspace(root) => (root.to_owned(), None),
+                    TodoFrom::Workspace(ref root) => (root.clone(), None),
                     TodoFrom::Dependency(ref reason) => {
                         let intermediate_root_dependency = reason
                             .intermediate_root_dependency
@@ -369,35 +856,51 @@ impl Resolved {
                     }
                 };
 
+                let depth = todo.depth + 1;
+                // NOTE: Up to depth 2, the parent is always the intermediate root dependency anyway
+                let deep = options.max_reason_depth.is_some_and(|max| depth > max) && depth > 2;
+                let parent = match intermediate_root_dependency {
+                    Some(ref intermediate) if deep => intermediate.clone(),
+                    _ => dep_parent.clone(),
+                };
+
                 Some(Todo {
                     kind: dep_kind,
                     incoming_edge: TodoFrom::Dependency(IncludedDependencyReason {
                         kind: package_kind,
                         root,
                         intermediate_root_dependency,
-                        parent: dep_parent.clone(),
+                        parent,
+                        deep,
                     }),
                     pkg: &dep.pkg,
+                    depth,
                 })
             }));
         }
+
+        Ok(())
     }
 
     /// Resolve everything from a given set of [`IndexedMetadata`]
+    ///
+    /// This fails if any of the selected workspace members in `options` doesn't exist.
     pub fn resolve_from_indexed(
         included: impl IntoIterator<Item: Borrow<IndexedMetadata>>,
-    ) -> Included {
+        options: &ResolveOptions,
+    ) -> Result<Included> {
         let mut out = Included::new();
         for included in included {
-            Self::resolve_platform(included.borrow(), &mut out);
+            Self::resolve_platform(included.borrow(), &mut out, options)?;
         }
-        out
+        Ok(out)
     }
 
-    /// Resolve the filtered dependencies from the given [`Included`] data and the set of
-    /// unfiltered [`IndexedMetadata`]
+    /// Resolve the filtered dependencies from the given [`Included`] data (resolved for the given
+    /// platforms) and the set of unfiltered [`IndexedMetadata`]
     pub fn resolve_filtered_from_indexed(
         included: Included,
+        platforms: BTreeSet<Platform>,
         full_metadata: IndexedMetadata,
     ) -> Self {
         assert_eq!(full_metadata.platform, None);
@@ -422,6 +925,7 @@ impl Resolved {
 
         Resolved {
             full_metadata,
+            platforms,
             included,
             filtered,
         }
@@ -432,26 +936,137 @@ impl Resolved {
         root_cargo_toml: &Path,
         specific_platforms: impl IntoIterator<Item = Platform>,
         include_all_platforms: bool,
+        options: &ResolveOptions,
+        cargo_options: &CargoOptions,
     ) -> Result<Self> {
-        let mut included = itertools::process_results(
-            specific_platforms
-                .into_iter()
-                .map(|platform| IndexedMetadata::gather(root_cargo_toml, Some(platform))),
-            |iter| Self::resolve_from_indexed(iter),
-        )?;
+        let platforms = specific_platforms.into_iter().collect::<BTreeSet<_>>();
+
+        // NOTE: The root manifest & the `Cargo.lock` are only read for the full metadata, the
+        // per-platform metadata reuses what was read from them
+        let full_metadata = IndexedMetadata::gather(root_cargo_toml, None, cargo_options)?;
+
+        // NOTE: Only up to `threads` metadata sets are kept in memory at once (next to the full
+        // metadata)
+        let mut included = Included::new();
+        let platform_list = platforms.iter().cloned().collect::<Vec<_>>();
+        // NOTE: Merging identical metadata keeps every distinct one until all are gathered instead
+        let mut merged = Vec::new();
+        for chunk in platform_list.chunks(cargo_options.threads().get()) {
+            let metadata = IndexedMetadata::gather_concurrently(
+                root_cargo_toml,
+                chunk,
+                &full_metadata,
+                cargo_options,
+            )?;
+            if options.merge_identical_platforms {
+                merged = IndexedMetadata::merge_identical(merged, metadata);
+                continue;
+            }
+            for metadata in metadata {
+                Self::resolve_platform(&metadata, &mut included, options)?;
+            }
+        }
+        for (metadata, platforms) in merged {
+            Self::resolve_platforms(&metadata, &platforms, &mut included, options)?;
+        }
 
-        let full_metadata = IndexedMetadata::gather(root_cargo_toml, None)?;
         let out = if include_all_platforms {
-            Self::resolve_platform(&full_metadata, &mut included);
+            Self::resolve_platform(&full_metadata, &mut included, options)?;
             Resolved {
                 full_metadata,
+                platforms,
                 included,
                 filtered: BTreeSet::new(),
             }
         } else {
-            Self::resolve_filtered_from_indexed(included, full_metadata)
+            Self::resolve_filtered_from_indexed(included, platforms, full_metadata)
         };
 
         Ok(out)
     }
+
+    /// Resolve the published source of the given crate version with the `Cargo.lock` it was
+    /// published with
+    ///
+    /// The source is fetched by `cargo metadata` for a temporary package depending on exactly that
+    /// version (instead of downloading the `.crate` file directly), and copied into a temporary
+    /// directory. This fails if the crate was published without a `Cargo.lock`. The temporary copy
+    /// is removed again before returning, so the paths in the `full_metadata` of the result don't
+    /// exist anymore.
+    pub fn resolve_published(
+        name: &str,
+        version: &Version,
+        specific_platforms: impl IntoIterator<Item = Platform>,
+        include_all_platforms: bool,
+        options: &ResolveOptions,
+        cargo_options: &CargoOptions,
+    ) -> Result<Self> {
+        let published = TempWorkspace::published(name, version, cargo_options)?;
+        Self::resolve_from_path(
+            &published.path().join("Cargo.toml"),
+            specific_platforms,
+            include_all_platforms,
+            options,
+            cargo_options,
+        )
+    }
+
+    /// Preview the resolution after doing all major updates for the direct dependencies of this
+    /// workspace, without changing the workspace itself
+    ///
+    /// The workspace (without its `target` & `.git` directories) gets copied into a new directory
+    /// in [`std::env::temp_dir`], where the manifests are edited & `cargo update` runs. That
+    /// directory is removed again before returning, even on errors. Path dependencies outside of
+    /// the workspace aren't supported, since they don't resolve relative to the copy. The
+    /// `full_metadata` of the preview still refers to the paths of the removed copy.
+    ///
+    /// The preview resolves for the same platforms as this resolution. Unlike the major updates
+    /// of the CLI, all updates are applied at once, and none of them are checked. With `as_of`,
+    /// only versions published up to that date are considered (see [`fetch_versions_for`]).
+    ///
+    /// [`fetch_versions_for`]: crate::major_updates::fetch_versions_for
+    pub fn preview_latest_majors(
+        &self,
+        client: &SyncClient,
+        as_of: Option<NaiveDate>,
+        include_all_platforms: bool,
+        options: &ResolveOptions,
+        cargo_options: &CargoOptions,
+    ) -> Result<Self> {
+        let workspace = TempWorkspace::copy_of(self.full_metadata.workspace_root.as_std_path())?;
+        let root_cargo_toml = workspace.path().join("Cargo.toml");
+
+        let metadata = IndexedMetadata::gather(&root_cargo_toml, None, cargo_options)?;
+        let mut manifest_deps = ManifestDependencySet::collect(&metadata)?;
+        // NOTE: Manifests outside of the workspace root aren't part of the temporary copy
+        manifest_deps.manifests.ensure_within(workspace.path())?;
+        for (name, mentions) in &mut manifest_deps.dependencies {
+            let latest = fetch_latest_major_update_for(
+                client,
+                name,
+                mentions.iter().map(DependencyMention::version),
+                as_of,
+                None,
+            )?;
+            if let LatestVersion::NewestUpdate(version) = latest {
+                manifest_deps
+                    .manifests
+                    .update_versions_in_memory(mentions, &version)?;
+            }
+        }
+        manifest_deps.manifests.write_back()?;
+
+        if let Err(failure) = update(&root_cargo_toml, false, cargo_options)? {
+            return Err(Report::from(ResolveDiffError::UpdateFailed(failure))
+                .wrap_err("Failed to preview the major updates"));
+        }
+
+        Self::resolve_from_path(
+            &root_cargo_toml,
+            self.platforms.iter().cloned(),
+            include_all_platforms,
+            options,
+            cargo_options,
+        )
+    }
 }
@@ -2,6 +2,7 @@
 
 //! Utilities for editing `Cargo.toml` manifests
 
+use crate::util::write_atomically;
 use color_eyre::Result;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -44,11 +45,9 @@ impl MutableTomlFile {
         &mut self.document
     }
 
+    /// Atomically replace the underlying file, see [`write_atomically`]
     fn write_back_inner(&self, data: &str) -> Result<()> {
-        let tmp_path = self.path.with_file_name(".Cargo.toml.update");
-        fs::write(&tmp_path, data)?;
-        fs::rename(&tmp_path, &self.path)?;
-        Ok(())
+        write_atomically(&self.path, data)
     }
 
     /// Write the TOML file back to the underlying file
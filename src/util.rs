@@ -2,34 +2,523 @@
 
 //! Various utility functions associated with this crate
 
-use crate::Platform;
 use crate::cmd::cmd;
+use crate::toml_edit::TomlPathLookup;
+use crate::{Platform, ResolveDiffError};
+use cargo_metadata::MetadataCommand;
 use color_eyre::Result;
-use std::path::{Path, PathBuf};
+use semver::Version;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::{self, Output},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+/// Options that get forwarded to every `cargo` invocation of this crate
+#[derive(Clone, Debug, Default)]
+pub struct CargoOptions {
+    /// Run `cargo` with `--offline`, so no network access happens
+    pub offline: bool,
+    /// Configuration overrides (`KEY=VALUE` or paths to extra configuration files), passed as
+    /// `--config` to `cargo`
+    pub config_overrides: Vec<String>,
+    /// Run `cargo metadata` without `--locked`, resolving missing or outdated parts of the
+    /// `Cargo.lock` freshly (based on the current registry state)
+    pub unlocked: bool,
+    /// The `cargo` binary to run instead of the one in `PATH`
+    pub cargo: Option<String>,
+    /// The `rustc` binary to run instead of the one in `PATH`
+    pub rustc: Option<String>,
+    /// The features to resolve dependencies (via `cargo metadata`) with
+    pub resolve_features: FeatureSelection,
+    /// The features to `cargo check` with (`cargo update` doesn't depend on features)
+    pub check_features: FeatureSelection,
+    /// The maximal number of `cargo` processes to run at once, defaulting to the available
+    /// parallelism, see [`CargoOptions::threads`]
+    pub threads: Option<NonZeroUsize>,
+    /// Where to record how long `cargo` invocations take, if at all
+    pub timings: Option<Arc<Timings>>,
+}
+
+/// The wall-clock durations of the phases of a run (such as `cargo metadata` for each platform),
+/// serialized as an object of lists per phase
+#[derive(Debug, Default)]
+pub struct Timings(Mutex<BTreeMap<&'static str, Vec<Timing>>>);
+
+/// A single duration recorded in [`Timings`]
+#[derive(Clone, Debug, Serialize)]
+pub struct Timing {
+    /// What the phase ran for (such as the platform or the crate of a major update)
+    pub label: Option<String>,
+    pub seconds: f64,
+}
+
+impl Timings {
+    /// Start timing a phase, which gets recorded once the returned guard is dropped
+    pub fn start(self: &Arc<Self>, phase: &'static str, label: Option<String>) -> TimingGuard {
+        TimingGuard {
+            timings: Some(Arc::clone(self)),
+            phase,
+            label,
+            start: Instant::now(),
+        }
+    }
+
+    fn record(&self, phase: &'static str, timing: Timing) {
+        let mut timings = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        timings.entry(phase).or_default().push(timing);
+    }
+}
+
+impl Serialize for Timings {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let timings = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        timings.serialize(serializer)
+    }
+}
+
+/// Records the duration since [`Timings::start`] when dropped (if there are any [`Timings`])
+pub struct TimingGuard {
+    timings: Option<Arc<Timings>>,
+    phase: &'static str,
+    label: Option<String>,
+    start: Instant,
+}
+
+impl Drop for TimingGuard {
+    fn drop(&mut self) {
+        if let Some(ref timings) = self.timings {
+            let timing = Timing {
+                label: self.label.take(),
+                seconds: self.start.elapsed().as_secs_f64(),
+            };
+            timings.record(self.phase, timing);
+        }
+    }
+}
+
+/// A selection of features for `cargo` commands that take them
+#[derive(Clone, Debug, Default)]
+pub struct FeatureSelection {
+    /// Pass `--all-features`
+    pub all_features: bool,
+    /// Pass `--no-default-features`
+    pub no_default_features: bool,
+    /// The features to pass via `--features`
+    pub features: Vec<String>,
+}
+
+impl FeatureSelection {
+    /// The arguments to pass to `cargo`
+    pub fn args(&self) -> impl Iterator<Item = &str> {
+        let features = (self.features.iter()).flat_map(|feature| ["--features", feature]);
+        (self.all_features.then_some("--all-features"))
+            .into_iter()
+            .chain(self.no_default_features.then_some("--no-default-features"))
+            .chain(features)
+    }
+}
+
+impl CargoOptions {
+    /// The `cargo` binary to run
+    pub fn cargo(&self) -> &str {
+        self.cargo.as_deref().unwrap_or("cargo")
+    }
+
+    /// The `rustc` binary to run
+    pub fn rustc(&self) -> &str {
+        self.rustc.as_deref().unwrap_or("rustc")
+    }
+
+    /// Start timing a phase if [`CargoOptions::timings`] are recorded, see [`Timings::start`]
+    pub fn time(&self, phase: &'static str, label: impl FnOnce() -> Option<String>) -> TimingGuard {
+        match self.timings {
+            Some(ref timings) => timings.start(phase, label()),
+            None => TimingGuard {
+                timings: None,
+                phase,
+                label: None,
+                start: Instant::now(),
+            },
+        }
+    }
+
+    /// The maximal number of `cargo` processes to run at once
+    pub fn threads(&self) -> NonZeroUsize {
+        self.threads
+            .unwrap_or_else(|| std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN))
+    }
+
+    /// The extra arguments to pass to `cargo`
+    pub fn args(&self) -> impl Iterator<Item = &str> {
+        let config = (self.config_overrides.iter()).flat_map(|config| ["--config", config]);
+        self.offline
+            .then_some("--offline")
+            .into_iter()
+            .chain(config)
+    }
+}
+
+/// The step of [`update`] that failed
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum UpdateFailure {
+    /// `cargo update` failed
+    UpdateFailed,
+    /// `cargo check` failed after a successful `cargo update`
+    CheckFailed,
+}
+
+/// A failed [`update`], with the captured stderr output of `cargo`
+#[derive(Clone, Debug, Serialize)]
+pub struct FailedUpdate {
+    pub reason: UpdateFailure,
+    pub error: String,
+}
+
+impl FailedUpdate {
+    /// Check the output of a `cargo` invocation, forwarding the captured stderr output
+    fn check_output(reason: UpdateFailure, output: Output) -> Result<Result<(), Self>> {
+        io::stderr().write_all(&output.stdout)?;
+        io::stderr().write_all(&output.stderr)?;
+
+        if output.status.success() {
+            Ok(Ok(()))
+        } else {
+            Ok(Err(FailedUpdate {
+                reason,
+                error: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }))
+        }
+    }
+}
 
 /// Do a `cargo update` for the given root `Cargo.toml` manifest, optionally running `cargo check`
-/// and returning if it succeeded
-pub fn update(path: &Path, check: bool) -> Result<bool> {
-    if !cmd!([cargo update] ["--manifest-path" (path)] -> bool)? {
-        return Ok(false);
+/// and returning why it failed if it did
+pub fn update(
+    path: &Path,
+    check: bool,
+    options: &CargoOptions,
+) -> Result<Result<(), FailedUpdate>> {
+    let timing = options.time("update", || None);
+    let output = cmd!(
+        [(options.cargo()) update] ["--manifest-path" (path) {options.args()}] -> Output
+    )?;
+    drop(timing);
+    if let Err(failure) = FailedUpdate::check_output(UpdateFailure::UpdateFailed, output)? {
+        return Ok(Err(failure));
     }
 
-    if check && !cmd!([cargo check] ["--manifest-path" (path) "--all-targets"] -> bool)? {
+    if check {
+        return check_packages(path, &[], options);
+    }
+
+    Ok(Ok(()))
+}
+
+/// Returns whether `cargo update --breaking` is available, as detected from `cargo --version`
+///
+/// It's still unstable, so this requires a nightly (or locally built) `cargo` since 1.81.
+pub fn supports_update_breaking(options: &CargoOptions) -> Result<bool> {
+    let version = cmd!([(options.cargo()) "--version"] -> String)?;
+    let Some(version) = (version.split_whitespace().nth(1)).and_then(|v| v.parse::<Version>().ok())
+    else {
         return Ok(false);
+    };
+
+    let unstable = ["nightly", "dev"].contains(&version.pre.as_str());
+    let release = Version::new(version.major, version.minor, version.patch);
+    Ok(unstable && release >= Version::new(1, 81, 0))
+}
+
+/// Do a `cargo update --breaking` for the given root `Cargo.toml` manifest (which edits the
+/// version requirements of direct dependencies across major versions), optionally running
+/// `cargo check` and returning why it failed if it did
+///
+/// This requires a `cargo` that supports it, see [`supports_update_breaking`].
+pub fn update_breaking(
+    path: &Path,
+    check: bool,
+    options: &CargoOptions,
+) -> Result<Result<(), FailedUpdate>> {
+    let timing = options.time("update", || None);
+    let output = cmd!(
+        [(options.cargo()) update] ["--manifest-path" (path) "--breaking" "-Zunstable-options" {options.args()}] -> Output
+    )?;
+    drop(timing);
+    if let Err(failure) = FailedUpdate::check_output(UpdateFailure::UpdateFailed, output)? {
+        return Ok(Err(failure));
+    }
+
+    if check {
+        return check_packages(path, &[], options);
     }
 
-    Ok(true)
+    Ok(Ok(()))
+}
+
+/// Run `cargo check` for the given root `Cargo.toml` manifest, returning why it failed if it did
+///
+/// This only checks the given workspace members (via `-p`), or the default members if there are
+/// none.
+pub fn check_packages(
+    path: &Path,
+    packages: &[String],
+    options: &CargoOptions,
+) -> Result<Result<(), FailedUpdate>> {
+    let packages = packages.iter().flat_map(|package| ["-p", package.as_str()]);
+    let timing = options.time("check", || None);
+    let output = cmd!(
+        [(options.cargo()) check] ["--manifest-path" (path) "--all-targets" {packages} {options.check_features.args()} {options.args()}] -> Output
+    )?;
+    drop(timing);
+    FailedUpdate::check_output(UpdateFailure::CheckFailed, output)
+}
+
+/// Generate a `Cargo.lock` for the given root `Cargo.toml` manifest
+pub fn generate_lockfile(path: &Path, options: &CargoOptions) -> Result<()> {
+    cmd!([(options.cargo()) "generate-lockfile"] ["--manifest-path" (path) {options.args()}])
+}
+
+/// Adjust the `Cargo.lock` of the given root `Cargo.toml` manifest to changed requirements in the
+/// manifests, without updating anything that is still locked to a matching version
+pub fn update_workspace(path: &Path, options: &CargoOptions) -> Result<()> {
+    cmd!([(options.cargo()) update] ["--manifest-path" (path) "--workspace" {options.args()}])
 }
 
 /// Locate the root `Cargo.toml` from the current working directory
-pub fn locate_project() -> Result<PathBuf> {
-    let out =
-        cmd!([cargo "locate-project"] ["--workspace" "--message-format" plain] -> String)?.into();
+pub fn locate_project(options: &CargoOptions) -> Result<PathBuf> {
+    let out = cmd!([(options.cargo()) "locate-project"] ["--workspace" "--message-format" plain] -> String)?
+        .into();
     Ok(out)
 }
 
+/// Locate the root `Cargo.toml` of the workspace that the `Cargo.toml` at `path` belongs to
+pub fn locate_workspace_manifest(path: &Path, options: &CargoOptions) -> Result<PathBuf> {
+    let out = cmd!([(options.cargo()) "locate-project"] ["--workspace" "--message-format" plain "--manifest-path" (path)] -> String)?
+        .into();
+    Ok(out)
+}
+
+/// Atomically replace the file at `path` via a temporary file next to it.
+///
+/// The temporary file name is unique to this process & created exclusively, and it gets removed
+/// again if writing or renaming it fails.
+pub fn write_atomically(path: &Path, data: &str) -> Result<()> {
+    let file_name = path.file_name().expect("file paths end in a file name");
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}.resolvediff-update",
+        file_name.display(),
+        process::id()
+    ));
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)?;
+
+    let result = file
+        .write_all(data.as_bytes())
+        .and_then(|()| file.sync_all())
+        .and_then(|()| fs::rename(&tmp_path, path));
+
+    if result.is_err() {
+        // NOTE: The original error is more relevant than a failed cleanup
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    Ok(result?)
+}
+
 /// Return the host platform tuple
-pub fn host_platform() -> Result<Platform> {
-    let platform_tuple = cmd!([rustc "--print" "host-tuple"] -> String)?;
+pub fn host_platform(options: &CargoOptions) -> Result<Platform> {
+    let platform_tuple = cmd!([(options.rustc()) "--print" "host-tuple"] -> String)?;
     Ok(Platform(platform_tuple))
 }
+
+/// Return the `build.target` platforms configured for `cargo`, if any
+///
+/// Like `cargo`, this considers (in order of precedence) `--config build.target=...` overrides in
+/// [`CargoOptions::config_overrides`], `CARGO_BUILD_TARGET`, the `.cargo/config.toml` files in the
+/// current directory & its ancestors and `$CARGO_HOME/config.toml`. Configuration files given as
+/// overrides aren't read.
+pub fn configured_build_targets(options: &CargoOptions) -> Result<Vec<Platform>> {
+    fn targets(value: &toml_edit::Value) -> Option<Vec<Platform>> {
+        let platform = |value: &toml_edit::Value| Some(Platform(value.as_str()?.to_owned()));
+        match value.as_array() {
+            Some(array) => array.iter().map(platform).collect(),
+            None => Some(vec![platform(value)?]),
+        }
+    }
+
+    let overridden = (options.config_overrides.iter().rev())
+        .filter_map(|config| config.split_once('='))
+        .find(|(key, _)| key.trim() == "build.target");
+    if let Some((_, value)) = overridden {
+        return value
+            .parse()
+            .ok()
+            .and_then(|value| targets(&value))
+            .ok_or_else(|| {
+                ResolveDiffError::InvalidBuildTarget(format!("override {value:?}")).into()
+            });
+    }
+
+    if let Ok(target) = std::env::var("CARGO_BUILD_TARGET") {
+        return Ok(vec![Platform(target)]);
+    }
+
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::home_dir().map(|home| home.join(".cargo")));
+    let current_dir = std::env::current_dir()?;
+    let config_files = current_dir
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home)
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")]);
+
+    for path in config_files {
+        if !path.is_file() {
+            continue;
+        }
+
+        let config = fs::read_to_string(&path)?.parse::<toml_edit::DocumentMut>()?;
+        if let Some(target) = config.as_item().path_lookup(["build", "target"]) {
+            return (target.as_value()).and_then(targets).ok_or_else(|| {
+                ResolveDiffError::InvalidBuildTarget(format!("in {path:?}")).into()
+            });
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+/// Return the platform tuples of all targets installed via `rustup`
+pub fn installed_platforms() -> Result<Vec<Platform>> {
+    let targets = cmd!([rustup target list] ["--installed"] -> String)?;
+    let platforms = targets
+        .lines()
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+        .map(|target| Platform(target.to_owned()))
+        .collect();
+    Ok(platforms)
+}
+
+/// A copy of a workspace in a new directory in [`std::env::temp_dir`], which gets removed again
+/// when this is dropped
+///
+/// `target` & `.git` directories aren't copied.
+pub(crate) struct TempWorkspace {
+    path: PathBuf,
+}
+
+impl TempWorkspace {
+    /// Create a new, empty & process-unique temporary directory
+    pub fn new() -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "cargo-resolvediff-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+        fs::create_dir(&path)?;
+        Ok(TempWorkspace { path })
+    }
+
+    /// Copy the workspace at `root` into a new, process-unique temporary directory
+    pub fn copy_of(root: &Path) -> Result<Self> {
+        // NOTE: Created before copying so the directory also gets removed if copying fails
+        let out = TempWorkspace::new()?;
+        Self::copy_dir(root, &out.path)?;
+        Ok(out)
+    }
+
+    /// Download the published source of the given crate version from the registry (via `cargo
+    /// metadata` for a temporary package depending on exactly that version), and copy it into a
+    /// new temporary directory
+    ///
+    /// This fails if the crate was published without a `Cargo.lock`.
+    pub fn published(name: &str, version: &Version, options: &CargoOptions) -> Result<Self> {
+        let fetch = TempWorkspace::new()?;
+        let manifest = fetch.path().join("Cargo.toml");
+        fs::write(
+            &manifest,
+            format!(
+                "[package]\n\
+                name = \"cargo-resolvediff-fetch\"\n\
+                version = \"0.0.0\"\n\
+                edition = \"2021\"\n\
+                \n\
+                [lib]\n\
+                path = \"lib.rs\"\n\
+                \n\
+                [dependencies]\n\
+                {name} = \"={version}\"\n"
+            ),
+        )?;
+        fs::write(fetch.path().join("lib.rs"), "")?;
+
+        let metadata = MetadataCommand::new()
+            .cargo_path(options.cargo())
+            .manifest_path(&manifest)
+            .other_options(options.args().map(str::to_owned).collect::<Vec<_>>())
+            .exec()?;
+        let package = (metadata.packages.iter())
+            .find(|package| package.name.as_str() == name && package.version == *version)
+            .ok_or_else(|| ResolveDiffError::NotDownloaded(format!("{name} {version}")))?;
+
+        let source = package
+            .manifest_path
+            .parent()
+            .expect("there was a file name");
+        if !source.join("Cargo.lock").is_file() {
+            return Err(ResolveDiffError::NoPublishedLockfile(format!("{name} {version}")).into());
+        }
+
+        Self::copy_of(source.as_std_path())
+    }
+
+    fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let target = to.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                if entry.file_name() == "target" || entry.file_name() == ".git" {
+                    continue;
+                }
+                fs::create_dir(&target)?;
+                Self::copy_dir(&entry.path(), &target)?;
+            } else {
+                fs::copy(entry.path(), target)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempWorkspace {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_dir_all(&self.path) {
+            eprintln!(
+                "Failed to remove temporary workspace copy {:?}: {error}",
+                self.path
+            );
+        }
+    }
+}